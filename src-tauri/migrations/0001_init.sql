@@ -1,6 +1,4 @@
-PRAGMA journal_mode=WAL;
-PRAGMA foreign_keys=ON;
-
+-- journal_mode/foreign_keys are set per connection by db::open_conn.
 
 -- Minimal single-project schema (project_id fixed to 'p1' for alpha)
 CREATE TABLE IF NOT EXISTS Document(
@@ -28,6 +26,8 @@ updated_at TEXT DEFAULT CURRENT_TIMESTAMP
 );
 
 
+-- External-content index over Body, kept in sync by the triggers below.
+-- If it drifts, `INSERT INTO body_fts(body_fts) VALUES('rebuild')` repairs it.
 CREATE VIRTUAL TABLE IF NOT EXISTS body_fts USING fts5(markdown, content='Body', content_rowid='rowid');
 
 
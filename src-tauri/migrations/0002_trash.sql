@@ -0,0 +1,2 @@
+-- Soft-delete: trashed documents keep their rows until the trash is emptied.
+ALTER TABLE Document ADD COLUMN deleted_at TEXT;
@@ -0,0 +1,4 @@
+-- Manual chapter ordering within a folder.
+ALTER TABLE Document ADD COLUMN sort_order INTEGER;
+-- Existing rows keep their creation order (rowid grows with each insert).
+UPDATE Document SET sort_order = rowid;
@@ -0,0 +1,5 @@
+-- Per-project key/value preferences (author name, autosave interval, ...).
+CREATE TABLE IF NOT EXISTS Settings(
+key TEXT PRIMARY KEY,
+value TEXT
+);
@@ -0,0 +1,10 @@
+-- Directed links between characters (ally, rival, parent, ...).
+CREATE TABLE IF NOT EXISTS CharacterRelation(
+id TEXT PRIMARY KEY,
+from_char TEXT NOT NULL REFERENCES Character(id) ON DELETE CASCADE,
+to_char TEXT NOT NULL REFERENCES Character(id) ON DELETE CASCADE,
+kind TEXT NOT NULL,
+note TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_relation_from ON CharacterRelation(from_char);
+CREATE INDEX IF NOT EXISTS idx_relation_to ON CharacterRelation(to_char);
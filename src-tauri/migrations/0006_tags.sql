@@ -0,0 +1,11 @@
+-- Free-form document tags, on top of the folder tree.
+CREATE TABLE IF NOT EXISTS Tag(
+id TEXT PRIMARY KEY,
+name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS DocumentTag(
+document_id TEXT NOT NULL REFERENCES Document(id) ON DELETE CASCADE,
+tag_id TEXT NOT NULL REFERENCES Tag(id) ON DELETE CASCADE,
+PRIMARY KEY(document_id, tag_id)
+);
+CREATE INDEX IF NOT EXISTS idx_document_tag_tag ON DocumentTag(tag_id);
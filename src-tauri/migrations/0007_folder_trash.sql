@@ -0,0 +1,7 @@
+-- Soft delete for folders and characters. trash_folder stamps everything it
+-- trashes with one trash_batch_id so restore_folder can undo exactly that.
+ALTER TABLE Folder ADD COLUMN deleted_at TEXT;
+ALTER TABLE Folder ADD COLUMN trash_batch_id TEXT;
+ALTER TABLE Character ADD COLUMN deleted_at TEXT;
+ALTER TABLE Character ADD COLUMN trash_batch_id TEXT;
+ALTER TABLE Document ADD COLUMN trash_batch_id TEXT;
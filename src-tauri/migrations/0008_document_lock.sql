@@ -0,0 +1,2 @@
+-- Read-only flag for finished chapters.
+ALTER TABLE Document ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;
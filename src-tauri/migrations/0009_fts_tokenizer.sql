@@ -0,0 +1,11 @@
+-- Recreate body_fts with diacritics folded, so "cafe" finds "café" (unicode61
+-- already ignores case). The body_* triggers look body_fts up by name and keep
+-- working against the new table; 'rebuild' reindexes every existing Body.
+DROP TABLE IF EXISTS body_fts;
+CREATE VIRTUAL TABLE body_fts USING fts5(
+    markdown,
+    content='Body',
+    content_rowid='rowid',
+    tokenize='unicode61 remove_diacritics 2'
+);
+INSERT INTO body_fts(body_fts) VALUES('rebuild');
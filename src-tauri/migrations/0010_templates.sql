@@ -0,0 +1,5 @@
+-- Reusable bodies for new documents (chapter, scene, ...), keyed by name.
+CREATE TABLE IF NOT EXISTS Template(
+name TEXT PRIMARY KEY,
+markdown TEXT NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Where each document's md mirror lives, relative to md/ with `/` separators.
+-- Documents without a row are at the original flat `<id>.md`.
+CREATE TABLE IF NOT EXISTS MirrorPath(
+document_id TEXT PRIMARY KEY REFERENCES Document(id) ON DELETE CASCADE,
+path TEXT NOT NULL
+);
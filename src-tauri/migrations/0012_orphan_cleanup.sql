@@ -0,0 +1,9 @@
+-- Connections from before foreign_keys was switched on could delete a
+-- Document without its ON DELETE CASCADE firing. Clear out what that left.
+DELETE FROM Body WHERE document_id NOT IN (SELECT id FROM Document);
+DELETE FROM Snapshot WHERE document_id NOT IN (SELECT id FROM Document);
+DELETE FROM DocumentTag WHERE document_id NOT IN (SELECT id FROM Document)
+    OR tag_id NOT IN (SELECT id FROM Tag);
+DELETE FROM CharacterRelation WHERE from_char NOT IN (SELECT id FROM Character)
+    OR to_char NOT IN (SELECT id FROM Character);
+DELETE FROM MirrorPath WHERE document_id NOT IN (SELECT id FROM Document);
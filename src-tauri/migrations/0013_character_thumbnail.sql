@@ -0,0 +1,2 @@
+-- Small copy of the portrait for tree/list views, written by import_character_image.
+ALTER TABLE Character ADD COLUMN thumbnail_path TEXT;
@@ -0,0 +1,2 @@
+-- Workflow label for a document ("draft", "revising", "done"); NULL = unset.
+ALTER TABLE Document ADD COLUMN status TEXT;
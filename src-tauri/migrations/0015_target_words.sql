@@ -0,0 +1,3 @@
+-- Word-count goal for a document; NULL = none. The project-wide goal lives
+-- in Settings under `target_words`.
+ALTER TABLE Document ADD COLUMN target_words INTEGER;
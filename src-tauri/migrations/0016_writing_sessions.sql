@@ -0,0 +1,10 @@
+-- One row per start_session/end_session pair. words_added is the net change
+-- in word count across every document saved during the session; ended_at
+-- stays NULL while it's running (or if the app quit before end_session).
+CREATE TABLE IF NOT EXISTS WritingSession(
+id TEXT PRIMARY KEY,
+started_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+ended_at TEXT,
+words_added INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS idx_writing_session_started ON WritingSession(started_at);
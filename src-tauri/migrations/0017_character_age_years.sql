@@ -0,0 +1,5 @@
+-- Numeric age for sorting and filtering, set whenever the free-text `age` is
+-- a plain whole number; `age` stays what the user typed ("immortal", "40s").
+ALTER TABLE Character ADD COLUMN age_years INTEGER;
+UPDATE Character SET age_years = CAST(trim(age) AS INTEGER)
+WHERE trim(age) <> '' AND trim(age) NOT GLOB '*[^0-9]*' AND length(trim(age)) <= 9;
@@ -0,0 +1,11 @@
+-- Hard deletes that undo_last can still put back, newest last. payload is
+-- the deleted rows plus any files they owned, as JSON (see commands.rs).
+-- Trimmed to the newest few entries on every insert.
+CREATE TABLE IF NOT EXISTS UndoLog(
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+kind TEXT NOT NULL,
+target_id TEXT NOT NULL,
+label TEXT NOT NULL,
+payload TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Starred documents the writer wants at hand; list_pinned returns them.
+ALTER TABLE Document ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
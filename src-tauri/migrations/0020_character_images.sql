@@ -0,0 +1,11 @@
+-- Reference images for a character beyond the portrait in Character.image_path,
+-- added by import_character_images. Names are relative to the character's
+-- assets/characters/<id>/ dir so copies of the character can share the rows.
+CREATE TABLE IF NOT EXISTS CharacterImage(
+id TEXT PRIMARY KEY,
+character_id TEXT NOT NULL REFERENCES Character(id) ON DELETE CASCADE,
+file_name TEXT NOT NULL,
+thumbnail_name TEXT NOT NULL,
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+UNIQUE(character_id, file_name)
+);
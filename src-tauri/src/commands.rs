@@ -1,19 +1,104 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use chrono::Utc;
-use rusqlite::{params, Connection};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use tauri::{Emitter, Manager, State};
+use ts_rs::TS;
 
-use crate::db::{run_migrations, select_docs, select_folders, select_chars};
-use crate::fs_utils::atomic_write;
+use crate::error::AppError;
+use crate::db::{open_conn, run_migrations, with_tx, ActiveSession, schema_is_current, select_docs, select_pinned_docs, select_folders, select_chars, select_trashed_docs, select_trashed_folders, AppState};
+use crate::fs_utils::{atomic_write, commit_staged, stage_write};
 
 // ------- Types
-#[derive(Serialize, Deserialize)]
-pub struct Doc { pub id: String, pub title: String, pub folder_id: Option<String> }
+// Types deriving `TS` are mirrored into src/lib/bindings.ts for the frontend;
+// `cargo test` regenerates that file, so commit it alongside any change here.
 
-#[derive(Serialize, Deserialize)]
-pub struct Folder { pub id: String, pub name: String, pub parent_id: Option<String> }
+// A live document as list_tree reports it; `updated_at` is the later of the
+// row's and its body's.
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export, export_to = "bindings.ts")]
+pub struct Doc {
+    pub id: String,
+    pub title: String,
+    pub folder_id: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub locked: bool,
+    pub status: Option<String>,
+    pub pinned: bool,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export, export_to = "bindings.ts")]
+pub struct Folder {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    // Only filled in by list_tree with `include_counts`: live documents and
+    // their words anywhere under the folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub doc_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub word_count: Option<u32>,
+}
+
+// Just enough of a character to place it in the tree; load_character has the rest.
+#[derive(Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export, export_to = "bindings.ts")]
+pub struct CharacterSummary { pub id: String, pub name: String, pub folder_id: Option<String> }
+
+/// Everything live in the project, as flat lists (list_tree_nested nests them).
+#[derive(Serialize, TS)]
+#[ts(export, export_to = "bindings.ts")]
+pub struct Tree { pub docs: Vec<Doc>, pub folders: Vec<Folder>, pub characters: Vec<CharacterSummary> }
+
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "bindings.ts")]
+pub struct Attribute { pub key: String, pub value: String }
+
+#[derive(Serialize, TS)]
+#[ts(export, export_to = "bindings.ts")]
+pub struct SearchHit { pub id: String, pub title: String, pub snippet: String }
+
+// ts-rs maps i64 to bigint, but JSON numbers arrive as plain numbers.
+#[derive(Serialize, TS)]
+#[ts(export, export_to = "bindings.ts")]
+pub struct SearchPage { pub hits: Vec<SearchHit>, #[ts(type = "number")] pub total: i64 }
+
+/// Payload of the `tree-changed` event, emitted after every command that
+/// adds, removes, moves, renames or reorders something in the tree. `kind` is
+/// "created", "deleted", "trashed", "restored", "moved", "renamed" or
+/// "reordered"; the id lists name what it happened to (for folders, the root
+/// of the affected subtree).
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeChange {
+    pub kind: &'static str,
+    pub doc_ids: Vec<String>,
+    pub folder_ids: Vec<String>,
+    pub character_ids: Vec<String>,
+}
+
+impl TreeChange {
+    fn docs(kind: &'static str, ids: Vec<String>) -> Self {
+        TreeChange { kind, doc_ids: ids, folder_ids: Vec::new(), character_ids: Vec::new() }
+    }
+
+    fn folders(kind: &'static str, ids: Vec<String>) -> Self {
+        TreeChange { kind, doc_ids: Vec::new(), folder_ids: ids, character_ids: Vec::new() }
+    }
+
+    fn characters(kind: &'static str, ids: Vec<String>) -> Self {
+        TreeChange { kind, doc_ids: Vec::new(), folder_ids: Vec::new(), character_ids: ids }
+    }
+}
 
 // ------- Helpers
 fn new_id() -> String {
@@ -22,378 +107,4875 @@ fn new_id() -> String {
     format!("d{}", ns)
 }
 
-fn mirror_md(project_path: &str, doc_id: &str, md: &str) -> Result<(), String> {
-    let path = Path::new(project_path).join("md").join(format!("{doc_id}.md"));
-    atomic_write(&path, md.as_bytes()).map_err(|e| e.to_string())
+// Report `{ done, total }` on `event` so the frontend can draw a progress bar.
+// A window that has gone away just means nobody is watching.
+fn emit_progress(window: &tauri::Window, event: &str, done: usize, total: usize) {
+    let _ = window.emit(event, serde_json::json!({ "done": done, "total": total }));
+}
+
+// Tell every open view to refresh; like emit_progress, nobody listening is fine.
+fn emit_tree_changed(window: &tauri::Window, change: TreeChange) {
+    let _ = window.emit("tree-changed", change);
+}
+
+// Write the md mirror with YAML frontmatter (title, id, updated) ahead of the
+// body so the file is self-describing; the DB stays the source of truth. The
+// file goes wherever the `mirror_layout` setting puts it, moving an existing
+// mirror if its title or folder changed.
+fn mirror_md(conn: &Connection, project_path: &str, doc_id: &str, md: &str) -> Result<(), AppError> {
+    let (rel, contents) = mirror_target(conn, doc_id, md)?;
+
+    let md_dir = Path::new(project_path).join("md");
+    let recorded: Option<String> = conn
+        .query_row("SELECT path FROM MirrorPath WHERE document_id=?", [doc_id], |r| r.get(0))
+        .optional()?;
+    let old_rel = recorded.clone().unwrap_or_else(|| format!("{doc_id}.md"));
+
+    // A case-only rename is the same file on case-insensitive filesystems, so
+    // clear the old name before writing rather than after.
+    let case_only = old_rel != rel && old_rel.to_lowercase() == rel.to_lowercase();
+    if case_only {
+        fs::remove_file(md_dir.join(&old_rel)).ok();
+    }
+
+    let path = md_dir.join(&rel);
+    // Leave an identical mirror alone so its mtime only moves on real changes.
+    let unchanged = fs::read(&path).is_ok_and(|old| sha256_hex(&old) == sha256_hex(contents.as_bytes()));
+    if !unchanged {
+        atomic_write(&path, contents.as_bytes())?;
+    }
+    if recorded.as_deref() != Some(rel.as_str()) {
+        conn.execute(
+            "INSERT INTO MirrorPath(document_id, path) VALUES(?, ?)
+             ON CONFLICT(document_id) DO UPDATE SET path=excluded.path",
+            params![doc_id, rel],
+        )?;
+    }
+    if old_rel != rel && !case_only {
+        let old_path = md_dir.join(&old_rel);
+        fs::remove_file(&old_path).ok();
+        prune_empty_dirs(&md_dir, old_path.parent());
+    }
+    Ok(())
+}
+
+// Where `doc_id`'s mirror belongs (relative to md/) and what it should contain.
+fn mirror_target(conn: &Connection, doc_id: &str, md: &str) -> Result<(String, String), AppError> {
+    let (title, folder_id, updated): (String, Option<String>, Option<String>) = conn.query_row(
+        "SELECT Document.title, Document.folder_id, Body.updated_at
+         FROM Document LEFT JOIN Body ON Body.document_id = Document.id
+         WHERE Document.id=?",
+        [doc_id],
+        |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+    )?;
+    // A JSON string is also a valid YAML double-quoted scalar.
+    let quoted = serde_json::to_string(&title).unwrap_or_default();
+    let contents = format!(
+        "---\ntitle: {quoted}\nid: {doc_id}\nupdated: {}\n---\n\n{md}",
+        updated.unwrap_or_default()
+    );
+    let rel = if mirror_by_folder(conn)? {
+        folder_mirror_path(conn, doc_id, &title, folder_id.as_deref())?
+    } else {
+        format!("{doc_id}.md")
+    };
+    Ok((rel, contents))
+}
+
+// The `mirror_layout` setting: "folders" lays mirrors out as
+// `md/<folder path>/<title>.md`; anything else keeps the flat `md/<id>.md`.
+fn mirror_by_folder(conn: &Connection) -> Result<bool, AppError> {
+    let layout: Option<String> = conn
+        .query_row("SELECT value FROM Settings WHERE key='mirror_layout'", [], |r| r.get(0))
+        .optional()?
+        .flatten();
+    Ok(layout.as_deref() == Some("folders"))
+}
+
+// `<folder>/<subfolder>/<title>.md` for the folder layout, numbered like
+// `unique_name` when another document already has that path.
+fn folder_mirror_path(conn: &Connection, doc_id: &str, title: &str, folder_id: Option<&str>) -> Result<String, AppError> {
+    let mut segments = Vec::new();
+    let mut cursor = folder_id.map(str::to_string);
+    let mut seen = std::collections::HashSet::new();
+    while let Some(id) = cursor {
+        if !seen.insert(id.clone()) {
+            break; // corrupt hierarchy; don't spin forever
+        }
+        let row: Option<(String, Option<String>)> = conn
+            .query_row("SELECT name, parent_id FROM Folder WHERE id=?", [&id], |r| Ok((r.get(0)?, r.get(1)?)))
+            .optional()?;
+        let Some((name, parent)) = row else { break };
+        segments.push(sanitize_filename(&name));
+        cursor = parent;
+    }
+    segments.reverse();
+    let dir: String = segments.iter().map(|s| format!("{s}/")).collect();
+
+    let base = sanitize_filename(title);
+    let mut n = 1;
+    loop {
+        let rel = if n == 1 { format!("{dir}{base}.md") } else { format!("{dir}{base} ({n}).md") };
+        let taken: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM MirrorPath WHERE lower(path)=lower(?) AND document_id<>?",
+            params![rel, doc_id],
+            |r| r.get(0),
+        )?;
+        if taken == 0 {
+            return Ok(rel);
+        }
+        n += 1;
+    }
+}
+
+// Remove `dir` and then its parents while they're empty, stopping at `root`.
+fn prune_empty_dirs(root: &Path, dir: Option<&Path>) {
+    let mut cursor = dir;
+    while let Some(d) = cursor {
+        if d == root || !d.starts_with(root) || fs::remove_dir(d).is_err() {
+            break;
+        }
+        cursor = d.parent();
+    }
+}
+
+// Path of a document's current mirror, wherever the layout put it.
+fn mirror_path(conn: &Connection, project_path: &str, doc_id: &str) -> Result<PathBuf, AppError> {
+    let rel: Option<String> = conn
+        .query_row("SELECT path FROM MirrorPath WHERE document_id=?", [doc_id], |r| r.get(0))
+        .optional()?;
+    let rel = rel.unwrap_or_else(|| format!("{doc_id}.md"));
+    Ok(Path::new(project_path).join("md").join(rel))
+}
+
+// Rewrite the mirrors of `doc_ids` after something that feeds their folder
+// layout path changed; a no-op for the flat layout.
+fn remirror_docs(conn: &Connection, project_path: &str, doc_ids: &[String]) -> Result<(), AppError> {
+    if !mirror_by_folder(conn)? {
+        return Ok(());
+    }
+    for id in doc_ids {
+        let md: Option<String> = conn
+            .query_row("SELECT markdown FROM Body WHERE document_id=?", [id], |r| r.get(0))
+            .optional()?;
+        mirror_md(conn, project_path, id, &md.unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+// Split a leading `---` frontmatter block off `text`; returns its `title`
+// (if any) and the remaining body. Text without frontmatter comes back whole.
+fn split_frontmatter(text: &str) -> (Option<String>, &str) {
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return (None, text);
+    };
+    let Some(end) = rest.find("\n---") else {
+        return (None, text);
+    };
+    let header = &rest[..end];
+    // Drop the rest of the closing `---` line, then the blank line mirror_md adds.
+    let after = &rest[end + 4..];
+    let body = after.strip_prefix("\r\n").or_else(|| after.strip_prefix('\n')).unwrap_or(after);
+    let body = body.strip_prefix("\r\n").or_else(|| body.strip_prefix('\n')).unwrap_or(body);
+
+    let title = header.lines().find_map(|line| {
+        let value = line.strip_prefix("title:")?.trim();
+        if value.starts_with('"') {
+            serde_json::from_str::<String>(value).ok()
+        } else {
+            Some(value.trim_matches('\'').to_string())
+        }
+    });
+    (title.filter(|t| !t.is_empty()), body)
+}
+
+// Reduce markdown to its prose: drop heading markers, emphasis and link
+// targets so `[the ship](http://...)` counts as two words, not one blob.
+fn strip_markdown(md: &str) -> String {
+    let mut out = String::with_capacity(md.len());
+    for line in md.lines() {
+        let mut chars = line.trim_start().trim_start_matches('#').chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' | '_' | '[' => {}
+                '!' if chars.peek() == Some(&'[') => {}
+                ']' => {
+                    // `](url)`: skip the link target entirely.
+                    if chars.peek() == Some(&'(') {
+                        for t in chars.by_ref() {
+                            if t == ')' { break; }
+                        }
+                    }
+                }
+                _ => out.push(c),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// (words, chars, chars without whitespace) of a markdown body.
+fn text_stats(md: &str) -> (usize, usize, usize) {
+    let prose = strip_markdown(md);
+    let words = prose
+        .split_whitespace()
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .count();
+    let text = prose.trim_end_matches('\n');
+    let chars = text.chars().count();
+    let no_spaces = text.chars().filter(|c| !c.is_whitespace()).count();
+    (words, chars, no_spaces)
 }
 
-// Remove a document row and its markdown file.
+// Remove a document row. Returns its markdown file so the caller can delete
+// it once the DB change is committed.
 fn delete_doc_internal(
-    conn: &mut Connection,
+    conn: &Connection,
     project_path: &str,
     doc_id: &str,
-) -> Result<(), String> {
-    // Delete from DB (Body is removed via ON DELETE CASCADE).
-    conn.execute("DELETE FROM Document WHERE id=?", params![doc_id])
-        .map_err(|e| e.to_string())?;
-    // Remove the markdown file if it exists.
-    let md_path = Path::new(project_path).join("md").join(format!("{doc_id}.md"));
-    let _ = fs::remove_file(&md_path);
-    Ok(())
+) -> Result<PathBuf, AppError> {
+    let path = mirror_path(conn, project_path, doc_id)?;
+    // Delete from DB (Body, Snapshot, DocumentTag and MirrorPath rows go via ON DELETE CASCADE).
+    conn.execute("DELETE FROM Document WHERE id=?", params![doc_id])?;
+    Ok(path)
 }
 
-// Remove a character row and its asset directory.
+// Remove a character row; its relations and images cascade. Returns its asset
+// directory (same contract as above).
 fn delete_character_internal(
-    conn: &mut Connection,
+    conn: &Connection,
     project_path: &str,
     char_id: &str,
-) -> Result<(), String> {
-    conn.execute("DELETE FROM Character WHERE id=?", params![char_id])
-        .map_err(|e| e.to_string())?;
-    let dir = Path::new(project_path)
+) -> Result<PathBuf, AppError> {
+    validate_char_id(char_id)?;
+    if conn.execute("DELETE FROM Character WHERE id=?", params![char_id])? == 0 {
+        return Err(AppError::NotFound("character does not exist".into()));
+    }
+    Ok(Path::new(project_path)
         .join("assets")
         .join("characters")
-        .join(char_id);
-    let _ = fs::remove_dir_all(&dir);
-    Ok(())
+        .join(char_id))
 }
 
-#[tauri::command]
-pub fn delete_doc(project_path: String, doc_id: String) -> Result<(), String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
-    delete_doc_internal(&mut conn, &project_path, &doc_id)
+// char_id becomes a directory name; don't let it climb out of assets/characters.
+fn validate_char_id(char_id: &str) -> Result<(), AppError> {
+    if char_id.is_empty() || char_id == "." || char_id == ".." || char_id.contains(['/', '\\']) {
+        return Err(AppError::Validation("invalid character id".into()));
+    }
+    Ok(())
 }
 
-#[tauri::command]
-pub fn delete_character(project_path: String, char_id: String) -> Result<(), String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
-    delete_character_internal(&mut conn, &project_path, &char_id)
+// Refuse to point a row at a folder that isn't there or is in the trash
+// (None = project root).
+fn ensure_folder_exists(conn: &Connection, folder_id: Option<&str>) -> Result<(), AppError> {
+    if let Some(fid) = folder_id {
+        let n: i64 = conn.query_row("SELECT COUNT(*) FROM Folder WHERE id=? AND deleted_at IS NULL", [fid], |r| r.get(0))?;
+        if n == 0 {
+            return Err(AppError::NotFound("target folder does not exist".into()));
+        }
+    }
+    Ok(())
 }
 
-#[tauri::command]
-pub fn delete_folder_recursive(
-    project_path: String,
-    folder_id: String,
-) -> Result<(), String> {
-    use std::path::Path;
-    use rusqlite::{params, Connection};
+// True for the project root, `md/`, `project.db` and anything outside the
+// project: paths a delete must never hit, whatever ids the frontend sends.
+fn is_protected_path(project_path: &str, path: &Path) -> bool {
+    // Resolve `.`/`..` lexically: the target may not exist, so canonicalize
+    // can't be relied on (`assets/characters/../../md` is still `md`).
+    let normalize = |p: &Path| {
+        let mut out = PathBuf::new();
+        for c in p.components() {
+            match c {
+                std::path::Component::CurDir => {}
+                std::path::Component::ParentDir => { out.pop(); }
+                c => out.push(c),
+            }
+        }
+        out
+    };
+    let root = normalize(Path::new(project_path));
+    let target = normalize(path);
+    !target.starts_with(&root)
+        || target == root
+        || target == root.join("md")
+        || target == root.join("project.db")
+}
 
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
+// Refuse to modify a document that's been locked via set_document_locked.
+fn ensure_unlocked(conn: &Connection, doc_id: &str) -> Result<(), AppError> {
+    let locked: Option<bool> = conn
+        .query_row("SELECT locked FROM Document WHERE id=?", [doc_id], |r| r.get(0))
+        .optional()?;
+    if locked == Some(true) {
+        return Err(AppError::Validation("document is locked".into()));
+    }
+    Ok(())
+}
 
-    // 1) Collect all descendant folder ids (BFS). Scope statements so they drop.
-    let mut to_delete = vec![folder_id.clone()];
+/// `folder_id` followed by all of its descendant folder ids (BFS order).
+fn folder_subtree(conn: &Connection, folder_id: &str) -> Result<Vec<String>, AppError> {
+    let mut ids = vec![folder_id.to_string()];
     let mut idx = 0;
-    while idx < to_delete.len() {
-        let current = to_delete[idx].clone();
+    while idx < ids.len() {
+        let current = ids[idx].clone();
         idx += 1;
 
-        // Scope ensures `st` and its iterator drop before next use of &mut conn
+        // Scope the statement so it drops before the next iteration.
         let child_ids: Vec<String> = {
             let mut st = conn
-                .prepare("SELECT id FROM Folder WHERE parent_id=?")
-                .map_err(|e| e.to_string())?;
+                .prepare("SELECT id FROM Folder WHERE parent_id=?")?;
             let rows = st
-                .query_map([current], |r| r.get::<_, String>(0))
-                .map_err(|e| e.to_string())?;
+                .query_map([current], |r| r.get::<_, String>(0))?;
             rows.filter_map(Result::ok).collect()
         };
 
-        to_delete.extend(child_ids);
+        ids.extend(child_ids);
     }
+    Ok(ids)
+}
 
-    // 2) For each folder, delete its docs (collect first, then mutate).
-    for fid in &to_delete {
-        let doc_ids: Vec<String> = {
-            let mut st = conn
-                .prepare("SELECT id FROM Document WHERE folder_id=?")
-                .map_err(|e| e.to_string())?;
-            let rows = st
-                .query_map([fid.clone()], |r| r.get::<_, String>(0))
-                .map_err(|e| e.to_string())?;
-            rows.filter_map(Result::ok).collect()
-        };
-
-        for doc_id in doc_ids {
-            // now it's safe to mutably borrow `conn`
-            delete_doc_internal(&mut conn, &project_path, &doc_id)?;
-        }
-
-        // 3) Delete characters in this folder (same pattern).
-        let char_ids: Vec<String> = {
-            let mut stc = conn
-                .prepare("SELECT id FROM Character WHERE folder_id=?")
-                .map_err(|e| e.to_string())?;
-            let rows = stc
-                .query_map([fid.clone()], |r| r.get::<_, String>(0))
-                .map_err(|e| e.to_string())?;
-            rows.filter_map(Result::ok).collect()
-        };
-
-        for char_id in char_ids {
-            delete_character_internal(&mut conn, &project_path, &char_id)?;
+// Best-effort removal of files/dirs left behind by committed deletes. Anything
+// is_protected_path refuses is left alone, whichever delete produced it.
+fn remove_paths(project_path: &str, paths: &[PathBuf]) {
+    for p in paths.iter().filter(|p| !is_protected_path(project_path, p)) {
+        if p.is_dir() {
+            let _ = fs::remove_dir_all(p);
+        } else {
+            let _ = fs::remove_file(p);
         }
     }
+}
 
-    // 4) Delete folders themselves (leaves first is safest).
-    for fid in to_delete.into_iter().rev() {
-        conn.execute("DELETE FROM Folder WHERE id=?", params![fid])
-            .map_err(|e| e.to_string())?;
+#[tauri::command]
+pub fn delete_doc(state: State<'_, AppState>, window: tauri::Window, project_path: String, doc_id: String) -> Result<(), AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+    ensure_unlocked(&conn, &doc_id)?;
+    let tx = conn.transaction()?;
+    let title: Option<String> = tx
+        .query_row("SELECT title FROM Document WHERE id=?", [&doc_id], |r| r.get(0))
+        .optional()?;
+    if let Some(title) = title {
+        let mut payload = UndoPayload::default();
+        capture_documents(&tx, std::slice::from_ref(&doc_id), &mut payload)?;
+        record_undo(&tx, "delete_doc", &doc_id, &title, &payload)?;
     }
-
+    let md_path = delete_doc_internal(&tx, &project_path, &doc_id)?;
+    tx.commit()?;
+    remove_paths(&project_path, &[md_path]);
+    emit_tree_changed(&window, TreeChange::docs("deleted", vec![doc_id]));
     Ok(())
 }
 
+#[tauri::command]
+pub fn delete_character(state: State<'_, AppState>, window: tauri::Window, project_path: String, char_id: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    let dir = delete_character_internal(&conn, &project_path, &char_id)?;
+    remove_paths(&project_path, &[dir]);
+    emit_tree_changed(&window, TreeChange::characters("deleted", vec![char_id]));
+    Ok(())
+}
 
-// ------- Commands
-
+// Hard-delete several documents at once; md files go only after the commit.
+// Unlike delete_doc this writes no undo entry.
 #[tauri::command]
-pub fn create_project(dir: String, name: String) -> Result<String, String> {
-    let base = Path::new(&dir).join(&name);
-    std::fs::create_dir_all(&base).map_err(|e| e.to_string())?;
-    std::fs::create_dir_all(base.join("md")).ok();
-    std::fs::create_dir_all(base.join("backups")).ok();
+pub fn delete_documents(state: State<'_, AppState>, window: tauri::Window, project_path: String, doc_ids: Vec<String>) -> Result<(), AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
 
-    let dbp = base.join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
-    run_migrations(&mut conn).map_err(|e| e.to_string())?;
+    let tx = conn.transaction()?;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for doc_id in &doc_ids {
+        ensure_unlocked(&tx, doc_id)?;
+        paths.push(delete_doc_internal(&tx, &project_path, doc_id)?);
+    }
+    tx.commit()?;
 
-    Ok(base.to_string_lossy().to_string())
+    remove_paths(&project_path, &paths);
+    emit_tree_changed(&window, TreeChange::docs("deleted", doc_ids));
+    Ok(())
 }
 
-#[tauri::command]
-pub fn open_project(dir: String) -> Result<String, String> { Ok(dir) }
+// ------- Trash (soft delete; `delete_doc` / `delete_folder_recursive` stay the hard deletes)
 
 #[tauri::command]
-pub fn list_tree(project_path: String) -> Result<serde_json::Value, String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
+pub fn trash_document(state: State<'_, AppState>, window: tauri::Window, project_path: String, doc_id: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    conn.execute(
+        "UPDATE Document SET deleted_at=CURRENT_TIMESTAMP WHERE id=? AND deleted_at IS NULL",
+        params![doc_id],
+    )?;
+    emit_tree_changed(&window, TreeChange::docs("trashed", vec![doc_id]));
+    Ok(())
+}
 
-    let docs = select_docs(&conn).map_err(|e| e.to_string())?;
-    let folders = select_folders(&conn).map_err(|e| e.to_string())?;
-    let chars = select_chars(&conn).map_err(|e| e.to_string())?;
+#[tauri::command]
+pub fn restore_trashed(state: State<'_, AppState>, window: tauri::Window, project_path: String, doc_id: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    // If the doc's folder was removed or trashed meanwhile, restore it to the root.
+    conn.execute(
+        "UPDATE Document
+         SET deleted_at=NULL, trash_batch_id=NULL,
+             folder_id=CASE WHEN folder_id IN (SELECT id FROM Folder WHERE deleted_at IS NULL)
+                            THEN folder_id ELSE NULL END
+         WHERE id=?",
+        params![doc_id],
+    )?;
+    emit_tree_changed(&window, TreeChange::docs("restored", vec![doc_id]));
+    Ok(())
+}
 
-    Ok(serde_json::json!({ "docs": docs, "folders": folders, "characters": chars }))
+#[tauri::command]
+pub fn list_trash(state: State<'_, AppState>, project_path: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let conn = state.conn(&project_path)?;
+    Ok(select_trashed_docs(&conn)?)
 }
 
+/// Hard-delete everything in the trash (documents, characters and folders);
+/// returns how many items were purged. This can't be undone with `undo_last`.
 #[tauri::command]
-pub fn create_document(project_path: String, title: String, folder_id: Option<String>) -> Result<String, String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
+pub fn empty_trash(state: State<'_, AppState>, window: tauri::Window, project_path: String) -> Result<usize, AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
 
-    let id = new_id();
-    conn.execute(
-        "INSERT INTO Document(id, project_id, folder_id, title) VALUES(?, 'p1', ?, ?)",
-        params![id, folder_id, title],
-    ).map_err(|e| e.to_string())?;
+    let tx = conn.transaction()?;
+    let (change, paths) = purge_trash(&tx, &project_path, None)?;
+    tx.commit()?;
 
-    conn.execute(
-        "INSERT INTO Body(document_id, markdown) VALUES(?, '# New Document')",
-        params![id],
-    ).map_err(|e| e.to_string())?;
+    remove_paths(&project_path, &paths);
+    let purged = change.doc_ids.len() + change.character_ids.len() + change.folder_ids.len();
+    emit_tree_changed(&window, change);
+    Ok(purged)
+}
 
-    mirror_md(&project_path, &id, "# New Document")?;
-    Ok(id)
+// Hard-delete trashed items, or only those trashed more than `older_than_days`
+// ago. Returns what went and the files to remove once the caller commits.
+fn purge_trash(conn: &Connection, project_path: &str, older_than_days: Option<i64>) -> Result<(TreeChange, Vec<PathBuf>), AppError> {
+    let cutoff = match older_than_days {
+        Some(days) => format!("AND deleted_at < datetime('now', '-{days} days')"),
+        None => String::new(),
+    };
+    let trashed = |table: &str| -> Result<Vec<String>, AppError> {
+        let mut st = conn.prepare(&format!("SELECT id FROM {table} WHERE deleted_at IS NOT NULL {cutoff}"))?;
+        let rows = st.query_map([], |r| r.get::<_, String>(0))?;
+        Ok(rows.filter_map(Result::ok).collect())
+    };
+    let doc_ids = trashed("Document")?;
+    let char_ids = trashed("Character")?;
+    let folder_ids = trashed("Folder")?;
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for doc_id in &doc_ids {
+        paths.push(delete_doc_internal(conn, project_path, doc_id)?);
+    }
+    for char_id in &char_ids {
+        paths.push(delete_character_internal(conn, project_path, char_id)?);
+    }
+    for fid in &folder_ids {
+        conn.execute("DELETE FROM Folder WHERE id=?", params![fid])?;
+    }
+    Ok((TreeChange { kind: "deleted", doc_ids, folder_ids, character_ids: char_ids }, paths))
 }
 
-#[tauri::command]
-pub fn create_folder(project_path: String, name: String, parent_id: Option<String>) -> Result<String, String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
+// Used when Settings has no (or no numeric) `trash_retention_days`.
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
 
-    let id = new_id();
-    conn.execute(
-        "INSERT INTO Folder(id, project_id, parent_id, name) VALUES(?, 'p1', ?, ?)",
-        params![id, parent_id, name],
-    ).map_err(|e| e.to_string())?;
+// Called from open_project: purge what has sat in the trash past the
+// `trash_retention_days` setting (0 keeps it forever). Returns how many
+// items went.
+fn purge_expired_trash(conn: &mut Connection, project_path: &str) -> Result<usize, AppError> {
+    let days = conn
+        .query_row("SELECT value FROM Settings WHERE key='trash_retention_days'", [], |r| r.get::<_, Option<String>>(0))
+        .optional()?
+        .flatten()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|d| *d >= 0)
+        .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS);
+    if days == 0 {
+        return Ok(0);
+    }
 
-    Ok(id)
+    let tx = conn.transaction()?;
+    let (change, paths) = purge_trash(&tx, project_path, Some(days))?;
+    tx.commit()?;
+    remove_paths(project_path, &paths);
+    Ok(change.doc_ids.len() + change.character_ids.len() + change.folder_ids.len())
 }
 
+/// Soft-delete a folder with all its descendants and their docs/characters.
+/// Everything trashed here shares one batch id, which `restore_folder` undoes
+/// as a unit; items already in the trash keep their own entry.
 #[tauri::command]
-pub fn load_document(project_path: String, doc_id: String) -> Result<String, String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
+pub fn trash_folder(state: State<'_, AppState>, window: tauri::Window, project_path: String, folder_id: String) -> Result<(), AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+    ensure_folder_exists(&conn, Some(&folder_id))?;
 
-    let mut st = conn.prepare("SELECT markdown FROM Body WHERE document_id=?").map_err(|e| e.to_string())?;
-    let md: String = st.query_row([doc_id], |r| r.get(0)).map_err(|e| e.to_string())?;
-    Ok(md)
+    let tx = conn.transaction()?;
+    let batch = new_id();
+    for fid in folder_subtree(&tx, &folder_id)? {
+        for sql in [
+            "UPDATE Folder SET deleted_at=CURRENT_TIMESTAMP, trash_batch_id=?1 WHERE id=?2 AND deleted_at IS NULL",
+            "UPDATE Document SET deleted_at=CURRENT_TIMESTAMP, trash_batch_id=?1 WHERE folder_id=?2 AND deleted_at IS NULL",
+            "UPDATE Character SET deleted_at=CURRENT_TIMESTAMP, trash_batch_id=?1 WHERE folder_id=?2 AND deleted_at IS NULL",
+        ] {
+            tx.execute(sql, params![batch, fid])?;
+        }
+    }
+    tx.commit()?;
+    emit_tree_changed(&window, TreeChange::folders("trashed", vec![folder_id]));
+    Ok(())
 }
 
+/// Undo the `trash_folder` call that trashed `folder_id`.
 #[tauri::command]
-pub fn save_document(project_path: String, doc_id: String, markdown: String) -> Result<(), String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
+pub fn restore_folder(state: State<'_, AppState>, window: tauri::Window, project_path: String, folder_id: String) -> Result<(), AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
 
-    conn.execute(
-        "UPDATE Body SET markdown=?, updated_at=CURRENT_TIMESTAMP WHERE document_id=?",
-        params![markdown, doc_id],
-    ).map_err(|e| e.to_string())?;
+    let batch: String = conn
+        .query_row(
+            "SELECT trash_batch_id FROM Folder WHERE id=? AND deleted_at IS NOT NULL",
+            [&folder_id],
+            |r| r.get::<_, Option<String>>(0),
+        )
+        .optional()?
+        .flatten()
+        .ok_or_else(|| AppError::NotFound("folder is not in the trash".into()))?;
 
-    mirror_md(&project_path, &doc_id, &markdown)?;
+    let tx = conn.transaction()?;
+    for table in ["Folder", "Document", "Character"] {
+        tx.execute(
+            &format!("UPDATE {table} SET deleted_at=NULL, trash_batch_id=NULL WHERE trash_batch_id=?"),
+            [&batch],
+        )?;
+    }
+    // Same rule as restore_trashed: a parent that's gone or trashed means the root.
+    tx.execute(
+        "UPDATE Folder
+         SET parent_id=CASE WHEN parent_id IN (SELECT id FROM Folder WHERE deleted_at IS NULL)
+                            THEN parent_id ELSE NULL END
+         WHERE id=?",
+        [&folder_id],
+    )?;
+    tx.commit()?;
+    emit_tree_changed(&window, TreeChange::folders("restored", vec![folder_id]));
     Ok(())
 }
 
 #[tauri::command]
-pub fn search(project_path: String, q: String) -> Result<Vec<(String, String)>, String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
-
-    let mut st = conn.prepare(
-        "SELECT Document.id, snippet(body_fts, -1, '<b>','</b>','…', 12)
-         FROM body_fts
-         JOIN Body ON body_fts.rowid = Body.rowid
-         JOIN Document ON Body.document_id = Document.id
-         WHERE body_fts MATCH ?
-         LIMIT 50",
-    ).map_err(|e| e.to_string())?;
-
-    let rows = st.query_map([q], |row| Ok((row.get(0)?, row.get(1)?))).map_err(|e| e.to_string())?;
-    Ok(rows.filter_map(|r| r.ok()).collect())
+pub fn list_trashed_folders(state: State<'_, AppState>, project_path: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let conn = state.conn(&project_path)?;
+    Ok(select_trashed_folders(&conn)?)
 }
 
 #[tauri::command]
-pub fn create_snapshot(project_path: String, doc_id: String, note: String) -> Result<(), String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
-
-    let md: String = conn.query_row("SELECT markdown FROM Body WHERE document_id=?", [doc_id.clone()], |r| r.get(0))
-        .map_err(|e| e.to_string())?;
-
-    let id = new_id();
-    conn.execute(
-        "INSERT INTO Snapshot(id, document_id, note, markdown) VALUES(?,?,?,?)",
-        params![id, doc_id, note, md],
-    ).map_err(|e| e.to_string())?;
+pub fn delete_folder_recursive(
+    state: State<'_, AppState>,
+    window: tauri::Window,
+    project_path: String,
+    folder_id: String,
+) -> Result<(), AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+    delete_folder_tree(&mut conn, &project_path, &folder_id)?;
+    emit_tree_changed(&window, TreeChange::folders("deleted", vec![folder_id]));
     Ok(())
 }
 
-#[tauri::command]
-pub fn backup_project(project_path: String) -> Result<(), String> {
-    use std::io::Write;
+// The body of delete_folder_recursive: the folder, its descendants and
+// everything in them, plus their md mirrors and character assets.
+fn delete_folder_tree(conn: &mut Connection, project_path: &str, folder_id: &str) -> Result<(), AppError> {
+    // All DB deletes happen in one transaction; files are only removed after
+    // it commits so a failure midway leaves both the DB and disk untouched.
+    let tx = conn.transaction()?;
+    let mut paths: Vec<PathBuf> = Vec::new();
 
-    let ts = Utc::now().format("%Y%m%d_%H%M%S");
-    let backup_path = Path::new(&project_path).join("backups").join(format!("backup_{ts}.zip"));
+    // 1) Collect all descendant folder ids.
+    let to_delete = folder_subtree(&tx, folder_id)?;
+
+    // Keep everything about to go in the undo log first.
+    let name: Option<String> = tx
+        .query_row("SELECT name FROM Folder WHERE id=?", [folder_id], |r| r.get(0))
+        .optional()?;
+    if let Some(name) = name {
+        let in_subtree = |table: &str| -> Result<Vec<String>, AppError> {
+            let placeholders = (1..=to_delete.len()).map(|i| format!("?{i}")).collect::<Vec<_>>().join(",");
+            let mut st = tx.prepare(&format!("SELECT id FROM \"{table}\" WHERE folder_id IN ({placeholders})"))?;
+            let rows = st.query_map(params_from_iter(&to_delete), |r| r.get::<_, String>(0))?;
+            Ok(rows.filter_map(Result::ok).collect())
+        };
+        let mut payload = UndoPayload::default();
+        let mut folders = capture_rows(&tx, "Folder", "id IN ({ids})", &to_delete)?;
+        // Back into folder_subtree's order, so each parent is restored before its children.
+        folders.rows.sort_by_key(|r| to_delete.iter().position(|id| r.get("id").and_then(|v| v.as_str()) == Some(id)));
+        payload.tables.push(folders);
+        let fits = capture_characters(&tx, project_path, &in_subtree("Character")?, &mut payload)?;
+        capture_documents(&tx, &in_subtree("Document")?, &mut payload)?;
+        if fits {
+            record_undo(&tx, "delete_folder", folder_id, &name, &payload)?;
+        }
+    }
 
-    let mut zipw = zip::ZipWriter::new(std::fs::File::create(&backup_path).map_err(|e| e.to_string())?);
-    let opts = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    // 2) For each folder, delete its docs (collect first, then mutate).
+    for fid in &to_delete {
+        let doc_ids: Vec<String> = {
+            let mut st = tx
+                .prepare("SELECT id FROM Document WHERE folder_id=?")?;
+            let rows = st
+                .query_map([fid.clone()], |r| r.get::<_, String>(0))?;
+            rows.filter_map(Result::ok).collect()
+        };
 
-    let dbp = Path::new(&project_path).join("project.db");
-    zipw.start_file("project.db", opts).map_err(|e| e.to_string())?;
-    let db_bytes = std::fs::read(&dbp).map_err(|e| e.to_string())?;
-    zipw.write_all(&db_bytes).map_err(|e| e.to_string())?;
+        for doc_id in doc_ids {
+            paths.push(delete_doc_internal(&tx, project_path, &doc_id)?);
+        }
 
-    let md_dir = Path::new(&project_path).join("md");
-    if md_dir.exists() {
-        for entry in walkdir::WalkDir::new(&md_dir).into_iter().flatten().filter(|e| e.file_type().is_file()) {
-            let rel = entry.path().strip_prefix(&project_path).unwrap();
-            zipw.start_file(rel.to_string_lossy(), opts).map_err(|e| e.to_string())?;
-            let bytes = std::fs::read(entry.path()).map_err(|e| e.to_string())?;
-            zipw.write_all(&bytes).map_err(|e| e.to_string())?;
+        // 3) Delete characters in this folder (same pattern).
+        let char_ids: Vec<String> = {
+            let mut stc = tx
+                .prepare("SELECT id FROM Character WHERE folder_id=?")?;
+            let rows = stc
+                .query_map([fid.clone()], |r| r.get::<_, String>(0))?;
+            rows.filter_map(Result::ok).collect()
+        };
+
+        for char_id in char_ids {
+            paths.push(delete_character_internal(&tx, project_path, &char_id)?);
         }
     }
 
-    zipw.finish().map_err(|e| e.to_string())?;
+    // Paths come from ids, so a bad id could resolve to something vital.
+    if paths.iter().any(|p| is_protected_path(project_path, p)) {
+        return Err(AppError::Validation("refusing to delete protected path".into()));
+    }
+
+    // 4) Delete folders themselves (leaves first is safest).
+    for fid in to_delete.into_iter().rev() {
+        tx.execute("DELETE FROM Folder WHERE id=?", params![fid])?;
+    }
+
+    tx.commit()?;
+    remove_paths(project_path, &paths);
     Ok(())
 }
 
-// ----------------- Characters
+// ------- Undo log (the last few hard deletes, kept so undo_last can put them back)
 
-#[tauri::command]
-pub fn create_character(project_path: String, name: String, folder_id: Option<String>) -> Result<String, String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
-    let id = new_id();
-    conn.execute(
-        "INSERT INTO Character(id, project_id, folder_id, name, age, nationality, sexuality, height, attributes, image_path)
-         VALUES(?, 'p1', ?, ?, '', '', '', '', '[]', '')",
-        params![id, folder_id, name],
-    ).map_err(|e| e.to_string())?;
-    Ok(id)
+const UNDO_LOG_MAX_ENTRIES: i64 = 20;
+// A delete whose rows and files come to more than this still goes ahead,
+// it just can't be undone.
+const UNDO_PAYLOAD_MAX_BYTES: usize = 32 * 1024 * 1024;
+// Link rows whose other end may have been deleted since; they're dropped on
+// undo rather than blocking it.
+const UNDO_OPTIONAL_TABLES: &[&str] = &["DocumentTag", "CharacterRelation"];
+
+#[derive(Serialize, Deserialize, Default)]
+struct UndoPayload {
+    // Restored in the order listed, tables and the rows within them alike, so
+    // referenced rows (and parent folders) come first.
+    tables: Vec<UndoTable>,
+    files: Vec<UndoFile>,
 }
 
-#[tauri::command]
-pub fn load_character(project_path: String, char_id: String) -> Result<serde_json::Value, String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
-    let mut st = conn.prepare(
-        "SELECT name, folder_id, age, nationality, sexuality, height, attributes, image_path
-         FROM Character WHERE id=?",
-    ).map_err(|e| e.to_string())?;
-    let result = st.query_row([char_id.clone()], |r| {
-        Ok(serde_json::json!({
-            "id": char_id,
-            "name": r.get::<_, String>(0)?,
-            "folderId": r.get::<_, Option<String>>(1)?,
-            "age": r.get::<_, Option<String>>(2)?,
-            "nationality": r.get::<_, Option<String>>(3)?,
-            "sexuality": r.get::<_, Option<String>>(4)?,
-            "height": r.get::<_, Option<String>>(5)?,
-            "attributes": r.get::<_, Option<String>>(6)?,
-            "image": r.get::<_, Option<String>>(7)?,
-        }))
-    }).map_err(|e| e.to_string())?;
-    Ok(result)
+#[derive(Serialize, Deserialize)]
+struct UndoTable {
+    table: String,
+    rows: Vec<serde_json::Map<String, serde_json::Value>>,
 }
 
-#[tauri::command]
-pub fn save_character(project_path: String, char_id: String, data: serde_json::Value) -> Result<(), String> {
-    let dbp = Path::new(&project_path).join("project.db");
-    let mut conn = Connection::open(&dbp).map_err(|e| e.to_string())?;
+// A file owned by a deleted row, relative to the project root.
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    path: String,
+    data: String,
+}
 
-    let age = data.get("age").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    let nationality = data.get("nationality").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    let sexuality = data.get("sexuality").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    let height = data.get("height").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    let attributes_value = match data.get("attributes") {
-        Some(v) => if v.is_string() { v.as_str().unwrap_or("").to_string() }
-                   else { serde_json::to_string(v).unwrap_or_else(|_| "[]".to_string()) },
-        None => "[]".to_string(),
-    };
-    let image = data.get("image").and_then(|v| v.as_str()).unwrap_or("").to_string();
+// Every column of `table` for rows matching `filter`, where `?1..?n` in
+// `filter` are bound to `ids`. BLOBs are kept as `{"blob": "<base64>"}`.
+fn capture_rows(conn: &Connection, table: &str, filter: &str, ids: &[String]) -> Result<UndoTable, AppError> {
+    use base64::Engine;
+    use rusqlite::types::ValueRef;
+    let mut rows = Vec::new();
+    if !ids.is_empty() {
+        let placeholders = (1..=ids.len()).map(|i| format!("?{i}")).collect::<Vec<_>>().join(",");
+        let sql = format!("SELECT * FROM \"{table}\" WHERE {}", filter.replace("{ids}", &placeholders));
+        let mut st = conn.prepare(&sql)?;
+        let names: Vec<String> = st.column_names().into_iter().map(String::from).collect();
+        let mapped = st.query_map(params_from_iter(ids), |r| {
+            let mut row = serde_json::Map::new();
+            for (i, name) in names.iter().enumerate() {
+                let v = match r.get_ref(i)? {
+                    ValueRef::Null => serde_json::Value::Null,
+                    ValueRef::Integer(n) => n.into(),
+                    ValueRef::Real(f) => f.into(),
+                    ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned().into(),
+                    ValueRef::Blob(b) => serde_json::json!({ "blob": base64::engine::general_purpose::STANDARD.encode(b) }),
+                };
+                row.insert(name.clone(), v);
+            }
+            Ok(row)
+        })?;
+        for row in mapped {
+            rows.push(row?);
+        }
+    }
+    Ok(UndoTable { table: table.to_string(), rows })
+}
 
-    conn.execute(
-        "UPDATE Character
-         SET age=?, nationality=?, sexuality=?, height=?, attributes=?, image_path=?, updated_at=CURRENT_TIMESTAMP
-         WHERE id=?",
-        params![age, nationality, sexuality, height, attributes_value, image, char_id],
-    ).map_err(|e| e.to_string())?;
+// Documents plus everything that cascades off them.
+fn capture_documents(conn: &Connection, doc_ids: &[String], payload: &mut UndoPayload) -> Result<(), AppError> {
+    payload.tables.push(capture_rows(conn, "Document", "id IN ({ids})", doc_ids)?);
+    for table in ["Body", "Snapshot", "DocumentTag", "MirrorPath"] {
+        payload.tables.push(capture_rows(conn, table, "document_id IN ({ids})", doc_ids)?);
+    }
     Ok(())
 }
 
-#[tauri::command]
-pub fn import_character_image(
-    project_path: String,
-    char_id: String,
-    source_path: String,
-) -> Result<String, String> {
-    use std::fs;
-    use std::path::{Path, PathBuf};
+// Characters, their relations and their asset files. Returns false if the
+// files alone would blow the payload cap, in which case nothing is read.
+fn capture_characters(
+    conn: &Connection,
+    project_path: &str,
+    char_ids: &[String],
+    payload: &mut UndoPayload,
+) -> Result<bool, AppError> {
+    use base64::Engine;
+    payload.tables.push(capture_rows(conn, "Character", "id IN ({ids})", char_ids)?);
+    payload.tables.push(capture_rows(conn, "CharacterImage", "character_id IN ({ids})", char_ids)?);
+    payload.tables.push(capture_rows(conn, "CharacterRelation", "from_char IN ({ids}) OR to_char IN ({ids})", char_ids)?);
 
-    if source_path.trim().is_empty() {
-        return Err("source_path is empty".into());
+    let root = Path::new(project_path);
+    let mut files = Vec::new();
+    let mut total: u64 = 0;
+    for id in char_ids {
+        let dir = root.join("assets").join("characters").join(id);
+        for entry in walkdir::WalkDir::new(&dir).into_iter().filter_map(Result::ok) {
+            if entry.file_type().is_file() {
+                total += entry.metadata().map(|m| m.len()).unwrap_or(0);
+                files.push(entry.into_path());
+            }
+        }
+    }
+    if total as usize > UNDO_PAYLOAD_MAX_BYTES {
+        return Ok(false);
+    }
+    for path in files {
+        let rel = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+        let data = base64::engine::general_purpose::STANDARD.encode(fs::read(&path)?);
+        payload.files.push(UndoFile { path: rel, data });
     }
+    Ok(true)
+}
 
-    let src = Path::new(&source_path);
-    if !src.exists() || !src.is_file() {
-        return Err("source file does not exist".into());
+// Add an undo entry and drop the oldest beyond the cap. An oversized payload
+// is skipped, leaving the delete it describes permanent.
+fn record_undo(conn: &Connection, kind: &str, target_id: &str, label: &str, payload: &UndoPayload) -> Result<(), AppError> {
+    let json = serde_json::to_string(payload).map_err(|e| AppError::Validation(e.to_string()))?;
+    if json.len() > UNDO_PAYLOAD_MAX_BYTES {
+        return Ok(());
     }
+    conn.execute(
+        "INSERT INTO UndoLog(kind, target_id, label, payload) VALUES(?,?,?,?)",
+        params![kind, target_id, label, json],
+    )?;
+    conn.execute(
+        "DELETE FROM UndoLog WHERE id NOT IN (SELECT id FROM UndoLog ORDER BY id DESC LIMIT ?)",
+        [UNDO_LOG_MAX_ENTRIES],
+    )?;
+    Ok(())
+}
 
-    // destination: PROJECT/assets/characters/<char_id>/<filename>
-    let dest_dir = Path::new(&project_path)
-        .join("assets")
-        .join("characters")
-        .join(&char_id);
-    fs::create_dir_all(&dest_dir).map_err(|e| e.to_string())?;
+fn json_to_sql(v: &serde_json::Value) -> Result<rusqlite::types::Value, AppError> {
+    use base64::Engine;
+    use rusqlite::types::Value;
+    Ok(match v {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Integer(*b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Real(n.as_f64().unwrap_or(0.0)),
+        },
+        serde_json::Value::String(s) => Value::Text(s.clone()),
+        // capture_rows' BLOB encoding; text columns always come back as strings.
+        serde_json::Value::Object(o) => match o.get("blob").and_then(|b| b.as_str()) {
+            Some(data) => Value::Blob(
+                base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| AppError::Validation(format!("undo entry has a corrupt blob: {e}")))?,
+            ),
+            None => Value::Text(v.to_string()),
+        },
+        other => Value::Text(other.to_string()),
+    })
+}
 
-    let filename = src.file_name().ok_or("invalid filename")?;
-    let dest_path: PathBuf = dest_dir.join(filename);
+fn restore_rows(conn: &Connection, t: &UndoTable) -> Result<(), AppError> {
+    let optional = UNDO_OPTIONAL_TABLES.contains(&t.table.as_str());
+    for row in &t.rows {
+        let cols = row.keys().map(|k| format!("\"{k}\"")).collect::<Vec<_>>().join(",");
+        let marks = vec!["?"; row.len()].join(",");
+        let sql = format!("INSERT INTO \"{}\"({cols}) VALUES({marks})", t.table);
+        let values = row.values().map(json_to_sql).collect::<Result<Vec<_>, _>>()?;
+        match conn.execute(&sql, params_from_iter(values)) {
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if optional && e.code == rusqlite::ErrorCode::ConstraintViolation => {}
+            r => {
+                r?;
+            }
+        }
+    }
+    Ok(())
+}
 
-    // copy (overwrite if same name already exists)
-    fs::copy(&src, &dest_path).map_err(|e| e.to_string())?;
+// Put the most recent delete_doc or delete_folder_recursive back: rows, md
+// mirrors and character assets. A parent folder that has since gone (or been
+// trashed) sends the restored item to the root, as restore_trashed does.
+// delete_documents, delete_character and emptying the trash aren't logged, so
+// those stay permanent.
+#[tauri::command]
+pub fn undo_last(state: State<'_, AppState>, window: tauri::Window, project_path: String) -> Result<serde_json::Value, AppError> {
+    use base64::Engine;
+    let mut conn = state.conn_mut(&project_path)?;
+    let (id, kind, target_id, label, json): (i64, String, String, String, String) = conn
+        .query_row(
+            "SELECT id, kind, target_id, label, payload FROM UndoLog ORDER BY id DESC LIMIT 1",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("nothing to undo".into()))?;
+    let payload: UndoPayload = serde_json::from_str(&json).map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let ids_of = |table: &str| -> Vec<String> {
+        payload
+            .tables
+            .iter()
+            .filter(|t| t.table == table)
+            .flat_map(|t| t.rows.iter().filter_map(|r| r.get("id")?.as_str().map(String::from)))
+            .collect()
+    };
+    let doc_ids = ids_of("Document");
+    let folder_ids = ids_of("Folder");
+    let char_ids = ids_of("Character");
+
+    let tx = conn.transaction()?;
+    for (table, ids) in [("Document", &doc_ids), ("Folder", &folder_ids), ("Character", &char_ids)] {
+        for rid in ids {
+            let n: i64 = tx.query_row(&format!("SELECT COUNT(*) FROM \"{table}\" WHERE id=?"), [rid], |r| r.get(0))?;
+            if n > 0 {
+                return Err(AppError::Conflict(format!("{} {rid} already exists", table.to_lowercase())));
+            }
+        }
+    }
+    for t in &payload.tables {
+        restore_rows(&tx, t)?;
+    }
+    let live_parent = "(SELECT id FROM Folder WHERE deleted_at IS NULL)";
+    match kind.as_str() {
+        "delete_doc" => {
+            tx.execute(&format!("UPDATE Document SET folder_id=NULL WHERE id=? AND folder_id NOT IN {live_parent}"), [&target_id])?;
+        }
+        _ => {
+            tx.execute(&format!("UPDATE Folder SET parent_id=NULL WHERE id=? AND parent_id NOT IN {live_parent}"), [&target_id])?;
+        }
+    }
+    tx.execute("DELETE FROM UndoLog WHERE id=?", [id])?;
+    tx.commit()?;
+
+    // The rows are back; files are best-effort, like the deletes that removed them.
+    for doc_id in &doc_ids {
+        let md: Option<String> = conn
+            .query_row("SELECT markdown FROM Body WHERE document_id=?", [doc_id], |r| r.get(0))
+            .optional()?;
+        let _ = mirror_md(&conn, &project_path, doc_id, &md.unwrap_or_default());
+    }
+    for f in &payload.files {
+        let Ok(data) = base64::engine::general_purpose::STANDARD.decode(&f.data) else { continue };
+        let _ = atomic_write(&Path::new(&project_path).join(&f.path), &data);
+    }
+
+    let change = if kind == "delete_doc" {
+        TreeChange::docs("restored", vec![target_id])
+    } else {
+        TreeChange::folders("restored", vec![target_id])
+    };
+    emit_tree_changed(&window, change);
+    Ok(serde_json::json!({ "kind": kind, "label": label }))
+}
+
+// ------- Commands
+
+// Seeded into Settings by create_project.
+const DEFAULT_SETTINGS: &[(&str, &str)] = &[
+    ("author_name", ""),
+    ("autosave_interval_secs", "30"),
+    ("export_format", "md"),
+    // Characters changed before save_document snapshots on its own; 0 = off.
+    ("autosnapshot_threshold", "0"),
+    // "flat" (md/<id>.md) or "folders" (md/<folder path>/<title>.md); run
+    // relayout_mirrors after changing it.
+    ("mirror_layout", "flat"),
+    // open_project purges trash older than this many days; 0 = keep forever.
+    ("trash_retention_days", "30"),
+];
+
+const PROJECT_NAME_MAX_CHARS: usize = 100;
+
+// Names Windows refuses for a file or directory, whatever the extension.
+const RESERVED_WINDOWS_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// `name` becomes a single directory under `dir`, so it must be one portable
+// path component. Returns it trimmed.
+fn validate_project_name(name: &str) -> Result<&str, AppError> {
+    let name = name.trim();
+    let invalid = |why: &str| Err(AppError::Validation(format!("invalid project name: {why}")));
+    if name.is_empty() {
+        return invalid("it is empty");
+    }
+    if name.chars().count() > PROJECT_NAME_MAX_CHARS {
+        return invalid(&format!("longer than {PROJECT_NAME_MAX_CHARS} characters"));
+    }
+    if name == "." || name == ".." {
+        return invalid("it would point outside the chosen folder");
+    }
+    if let Some(c) = name.chars().find(|c| matches!(c, '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*') || c.is_control()) {
+        return invalid(&format!("it contains {c:?}"));
+    }
+    if name.ends_with('.') {
+        return invalid("it ends with a dot");
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if RESERVED_WINDOWS_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        return invalid(&format!("{stem} is reserved on Windows"));
+    }
+    Ok(name)
+}
+
+/// Create `dir/name` as a new project. Refuses if one is already there unless
+/// `overwrite` is set, in which case its database, mirrors and assets are
+/// replaced; `backups/` is left alone so the old project can still be restored.
+#[tauri::command]
+pub fn create_project(state: State<'_, AppState>, dir: String, name: String, overwrite: Option<bool>) -> Result<String, AppError> {
+    let name = validate_project_name(&name)?;
+    let base = Path::new(&dir).join(name);
+    if base.join("project.db").exists() {
+        if !overwrite.unwrap_or(false) {
+            return Err(AppError::Conflict("a project already exists at this location".into()));
+        }
+        let base_str = base.to_string_lossy();
+        state.ensure_writable(&base_str)?;
+        // Pooled connections would keep writing to the unlinked file.
+        state.close(&base_str);
+        for f in ["project.db", "project.db-wal", "project.db-shm"] {
+            match fs::remove_file(base.join(f)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        for sub in ["md", "assets"] {
+            if base.join(sub).exists() {
+                fs::remove_dir_all(base.join(sub))?;
+            }
+        }
+    }
+    std::fs::create_dir_all(&base)?;
+    std::fs::create_dir_all(base.join("md")).ok();
+    std::fs::create_dir_all(base.join("backups")).ok();
+
+    let mut conn = open_conn(&base, false)?;
+    run_migrations(&mut conn)?;
+    for (key, value) in DEFAULT_SETTINGS {
+        conn.execute("INSERT OR IGNORE INTO Settings(key, value) VALUES(?, ?)", params![key, value])?;
+    }
+
+    Ok(base.to_string_lossy().to_string())
+}
+
+/// Check that `dir` holds a real project and bring its schema up to date.
+/// With `readonly`, connections open read-only and every mutating command
+/// is refused, e.g. for inspecting an extracted backup. Opening read-write
+/// also purges expired trash, reported in a `trash-purged` event.
+#[tauri::command]
+pub fn open_project(state: State<'_, AppState>, app: tauri::AppHandle, dir: String, readonly: Option<bool>) -> Result<String, AppError> {
+    if !Path::new(&dir).join("project.db").is_file() {
+        return Err(AppError::NotFound(format!("{dir} is not a project (no project.db)")));
+    }
+    let readonly = readonly.unwrap_or(false);
+
+    // Both the connection pragmas and any read fail on a file that isn't
+    // SQLite ("file is not a database").
+    let not_a_db = |e: &dyn std::fmt::Display| AppError::Validation(format!("project.db is not a valid database: {e}"));
+    let mut conn = open_conn(&dir, readonly).map_err(|e| not_a_db(&e))?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |r| r.get::<_, i64>(0))
+        .map_err(|e| not_a_db(&e))?;
+    if readonly {
+        // Migrating would write; an older schema can't be queried as-is.
+        if !schema_is_current(&conn)? {
+            return Err(AppError::Validation("project needs upgrading; open it read-write first".into()));
+        }
+    } else {
+        run_migrations(&mut conn)?;
+        // Housekeeping only: a failed purge leaves the trash as it was and the
+        // project still opens. Either way the outcome goes out as `trash-purged`.
+        let outcome = match purge_expired_trash(&mut conn, &dir) {
+            Ok(0) => None,
+            Ok(n) => Some(serde_json::json!({ "projectPath": dir, "purged": n, "error": null })),
+            Err(e) => Some(serde_json::json!({ "projectPath": dir, "purged": 0, "error": e.to_string() })),
+        };
+        if let Some(payload) = outcome {
+            let _ = app.emit("trash-purged", payload);
+        }
+    }
+    state.open(&dir, readonly)?;
+    // The project is open either way; a stale recents list isn't worth failing over.
+    let _ = record_opened_project(app, dir.clone());
+    Ok(dir)
+}
+
+#[tauri::command]
+pub fn get_setting(state: State<'_, AppState>, project_path: String, key: String) -> Result<Option<String>, AppError> {
+    let conn = state.conn(&project_path)?;
+    let value = conn
+        .query_row("SELECT value FROM Settings WHERE key=?", [key], |r| r.get::<_, Option<String>>(0))
+        .optional()?;
+    Ok(value.flatten())
+}
+
+#[tauri::command]
+pub fn set_setting(state: State<'_, AppState>, project_path: String, key: String, value: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    conn.execute(
+        "INSERT INTO Settings(key, value) VALUES(?, ?)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+// ------- Recent projects (app-wide, kept in the app config dir rather than any project)
+
+const RECENT_PROJECTS_FILE: &str = "recent_projects.json";
+const RECENT_PROJECTS_MAX: usize = 20;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecentEntry {
+    path: String,
+    opened_at: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentProject {
+    pub path: String,
+    pub opened_at: String,
+    /// The directory (or its project.db) is gone, e.g. moved or on an unplugged drive.
+    pub missing: bool,
+}
+
+fn recent_projects_file(app: &tauri::AppHandle) -> Result<PathBuf, AppError> {
+    let dir = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| AppError::Io(std::io::Error::other(e.to_string())))?;
+    Ok(dir.join(RECENT_PROJECTS_FILE))
+}
+
+// Most recent first. A missing or unreadable file is just an empty list; the
+// start screen shouldn't fail over it.
+fn read_recent(app: &tauri::AppHandle) -> Result<Vec<RecentEntry>, AppError> {
+    let bytes = match fs::read(recent_projects_file(app)?) {
+        Ok(b) => b,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(serde_json::from_slice(&bytes).unwrap_or_default())
+}
+
+fn write_recent(app: &tauri::AppHandle, entries: &[RecentEntry]) -> Result<(), AppError> {
+    let json = serde_json::to_vec_pretty(entries).expect("recent projects serialize");
+    atomic_write(&recent_projects_file(app)?, &json)?;
+    Ok(())
+}
+
+/// Move `path` to the top of the recent list (adding it if new), keeping the
+/// newest `RECENT_PROJECTS_MAX`.
+#[tauri::command]
+pub fn record_opened_project(app: tauri::AppHandle, path: String) -> Result<(), AppError> {
+    let mut entries = read_recent(&app)?;
+    entries.retain(|e| e.path != path);
+    entries.insert(0, RecentEntry { path, opened_at: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string() });
+    entries.truncate(RECENT_PROJECTS_MAX);
+    write_recent(&app, &entries)
+}
+
+#[tauri::command]
+pub fn list_recent_projects(app: tauri::AppHandle) -> Result<Vec<RecentProject>, AppError> {
+    Ok(read_recent(&app)?
+        .into_iter()
+        .map(|e| RecentProject {
+            missing: !Path::new(&e.path).join("project.db").is_file(),
+            path: e.path,
+            opened_at: e.opened_at,
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn remove_recent_project(app: tauri::AppHandle, path: String) -> Result<(), AppError> {
+    let mut entries = read_recent(&app)?;
+    entries.retain(|e| e.path != path);
+    write_recent(&app, &entries)
+}
+
+#[tauri::command]
+pub fn list_tree(state: State<'_, AppState>, project_path: String, include_counts: Option<bool>) -> Result<Tree, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let docs = select_docs(&conn)?;
+    let mut folders = select_folders(&conn)?;
+    let characters = select_chars(&conn)?;
+    if include_counts.unwrap_or(false) {
+        annotate_folder_counts(&conn, &mut folders)?;
+    }
+
+    Ok(Tree { docs, folders, characters })
+}
+
+// Fill in each folder's recursive document and word counts: one pass over the
+// bodies for per-folder totals, then each folder's totals are added to all of
+// its ancestors. Documents under a trashed folder count for nothing.
+fn annotate_folder_counts(conn: &Connection, folders: &mut [Folder]) -> Result<(), AppError> {
+    use std::collections::HashMap;
+
+    let mut direct: HashMap<String, (u32, u32)> = HashMap::new();
+    let mut st = conn.prepare(
+        "SELECT Document.folder_id, COALESCE(Body.markdown, '')
+         FROM Document LEFT JOIN Body ON Body.document_id = Document.id
+         WHERE Document.deleted_at IS NULL AND Document.folder_id IS NOT NULL",
+    )?;
+    let rows = st.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?;
+    for (folder_id, md) in rows.filter_map(Result::ok) {
+        let entry = direct.entry(folder_id).or_default();
+        entry.0 += 1;
+        entry.1 = entry.1.saturating_add(text_stats(&md).0 as u32);
+    }
+
+    let parent: HashMap<String, Option<String>> =
+        folders.iter().map(|f| (f.id.clone(), f.parent_id.clone())).collect();
+    let mut totals: HashMap<String, (u32, u32)> = HashMap::new();
+    for (folder_id, (docs, words)) in &direct {
+        let mut current = Some(folder_id.clone());
+        // The hop limit stops a corrupt parent cycle from looping forever.
+        for _ in 0..=parent.len() {
+            let Some(id) = current else { break };
+            let Some(up) = parent.get(&id) else { break };
+            let total = totals.entry(id).or_default();
+            total.0 += docs;
+            total.1 = total.1.saturating_add(*words);
+            current = up.clone();
+        }
+    }
+
+    for f in folders.iter_mut() {
+        let (docs, words) = totals.get(&f.id).copied().unwrap_or_default();
+        f.doc_count = Some(docs);
+        f.word_count = Some(words);
+    }
+    Ok(())
+}
+
+/// `list_tree` as an actual tree: the root and every folder carry `folders`,
+/// `docs` and `characters` arrays of their direct children, in the same order
+/// as the flat lists. Items whose parent is missing land at the root.
+#[tauri::command]
+pub fn list_tree_nested(state: State<'_, AppState>, project_path: String) -> Result<serde_json::Value, AppError> {
+    use std::collections::{HashMap, HashSet};
+    use serde_json::Value;
+
+    let conn = state.conn(&project_path)?;
+    let folders: Vec<Value> = select_folders(&conn)?.iter().map(|f| serde_json::json!(f)).collect();
+    let docs: Vec<Value> = select_docs(&conn)?.iter().map(|d| serde_json::json!(d)).collect();
+    let chars: Vec<Value> = select_chars(&conn)?.iter().map(|c| serde_json::json!(c)).collect();
+
+    let known: HashSet<&str> = folders.iter().filter_map(|f| f["id"].as_str()).collect();
+    // Bucket each item under its parent folder id (None = root) in one pass.
+    let parent_of = |v: &Value, key: &str| v[key].as_str().filter(|id| known.contains(id)).map(str::to_string);
+    let mut child_folders: HashMap<Option<String>, Vec<Value>> = HashMap::new();
+    let mut child_docs: HashMap<Option<String>, Vec<Value>> = HashMap::new();
+    let mut child_chars: HashMap<Option<String>, Vec<Value>> = HashMap::new();
+    for f in &folders {
+        child_folders.entry(parent_of(f, "parentId")).or_default().push(f.clone());
+    }
+    for d in docs {
+        child_docs.entry(parent_of(&d, "folderId")).or_default().push(d);
+    }
+    for c in chars {
+        child_chars.entry(parent_of(&c, "folderId")).or_default().push(c);
+    }
+
+    // `seen` stops a corrupt parent cycle from recursing forever.
+    fn build(
+        key: Option<String>,
+        node: &mut Value,
+        folders: &mut HashMap<Option<String>, Vec<Value>>,
+        docs: &mut HashMap<Option<String>, Vec<Value>>,
+        chars: &mut HashMap<Option<String>, Vec<Value>>,
+        seen: &mut HashSet<String>,
+    ) {
+        let mut kids = folders.remove(&key).unwrap_or_default();
+        for kid in kids.iter_mut() {
+            let id = kid["id"].as_str().unwrap_or_default().to_string();
+            if seen.insert(id.clone()) {
+                build(Some(id), kid, folders, docs, chars, seen);
+            }
+        }
+        node["folders"] = Value::Array(kids);
+        node["docs"] = Value::Array(docs.remove(&key).unwrap_or_default());
+        node["characters"] = Value::Array(chars.remove(&key).unwrap_or_default());
+    }
+
+    let mut root = serde_json::json!({});
+    let mut seen = HashSet::new();
+    build(None, &mut root, &mut child_folders, &mut child_docs, &mut child_chars, &mut seen);
+    Ok(root)
+}
+
+#[tauri::command]
+pub fn create_document(state: State<'_, AppState>, window: tauri::Window, project_path: String, title: String, folder_id: Option<String>) -> Result<String, AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+    let id = insert_document(&mut conn, &project_path, &title, folder_id.as_deref(), "# New Document")?;
+    emit_tree_changed(&window, TreeChange::docs("created", vec![id.clone()]));
+    Ok(id)
+}
+
+// Shared by create_document and create_document_from_template. Either both
+// the rows and the md mirror end up existing or neither does: the mirror is
+// staged beside its final path inside the transaction, renamed into place
+// once the rows commit, and the rows are deleted again if that rename fails.
+fn insert_document(conn: &mut Connection, project_path: &str, title: &str, folder_id: Option<&str>, markdown: &str) -> Result<String, AppError> {
+    let id = new_id();
+    let md_dir = Path::new(project_path).join("md");
+    // Kept outside the closure so a failed commit can still clean it up.
+    let mut staged: Option<PathBuf> = None;
+
+    let inserted = with_tx(conn, |tx| -> Result<(PathBuf, PathBuf), AppError> {
+        tx.execute(
+            "INSERT INTO Document(id, project_id, folder_id, title, sort_order)
+             VALUES(?, 'p1', ?, ?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM Document))",
+            params![id, folder_id, title],
+        )?;
+
+        tx.execute(
+            "INSERT INTO Body(document_id, markdown) VALUES(?, ?)",
+            params![id, markdown],
+        )?;
+
+        let (rel, contents) = mirror_target(tx, &id, markdown)?;
+        tx.execute("INSERT INTO MirrorPath(document_id, path) VALUES(?, ?)", params![id, rel])?;
+        let path = md_dir.join(&rel);
+        let tmp = stage_write(&path, contents.as_bytes())?;
+        staged = Some(tmp.clone());
+        Ok((tmp, path))
+    });
+
+    let (tmp, path) = match inserted {
+        Ok(paths) => paths,
+        Err(e) => {
+            if let Some(tmp) = &staged {
+                fs::remove_file(tmp).ok();
+            }
+            return Err(e);
+        }
+    };
+    if let Err(e) = commit_staged(&tmp, &path) {
+        // Committed without a mirror; undo the rows (Body and MirrorPath cascade).
+        let _ = conn.execute("DELETE FROM Document WHERE id=?", [&id]);
+        return Err(e.into());
+    }
+    Ok(id)
+}
+
+// Copy a document's row and body under a fresh id; snapshots stay with the original.
+#[tauri::command]
+pub fn duplicate_document(state: State<'_, AppState>, window: tauri::Window, project_path: String, doc_id: String) -> Result<String, AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+
+    let (title, folder_id, md): (String, Option<String>, String) = conn.query_row(
+        "SELECT Document.title, Document.folder_id, Body.markdown
+         FROM Document JOIN Body ON Body.document_id = Document.id
+         WHERE Document.id=?",
+        [doc_id],
+        |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+    )?;
+
+    let id = new_id();
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO Document(id, project_id, folder_id, title, sort_order)
+         VALUES(?, 'p1', ?, ?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM Document))",
+        params![id, folder_id, format!("{title} (copy)")],
+    )?;
+    tx.execute(
+        "INSERT INTO Body(document_id, markdown) VALUES(?, ?)",
+        params![id, md],
+    )?;
+    tx.commit()?;
+
+    mirror_md(&conn, &project_path, &id, &md)?;
+    emit_tree_changed(&window, TreeChange::docs("created", vec![id.clone()]));
+    Ok(id)
+}
+
+/// Create a document for each `*.md` file directly inside `source_dir` (title =
+/// frontmatter `title`, else the file stem), in filename order. Subdirectories
+/// and other files are skipped. All rows go in one transaction; md mirrors are
+/// written once it commits.
+#[tauri::command]
+pub fn import_markdown_dir(
+    state: State<'_, AppState>,
+    window: tauri::Window,
+    project_path: String,
+    source_dir: String,
+    folder_id: Option<String>,
+) -> Result<Vec<String>, AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+
+    let src = Path::new(&source_dir);
+    if !src.is_dir() {
+        return Err(AppError::NotFound("source directory does not exist".into()));
+    }
+    ensure_folder_exists(&conn, folder_id.as_deref())?;
+
+    let mut files: Vec<PathBuf> = fs::read_dir(src)?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| e.eq_ignore_ascii_case("md")))
+        .collect();
+    files.sort();
+
+    let mut imported: Vec<(String, String)> = Vec::new();
+    let tx = conn.transaction()?;
+    for path in &files {
+        let text = fs::read_to_string(path)?;
+        // Files exported from a project carry their title in frontmatter.
+        let (fm_title, body) = split_frontmatter(&text);
+        let title = fm_title
+            .unwrap_or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default());
+        let md = body.to_string();
+
+        let id = new_id();
+        tx.execute(
+            "INSERT INTO Document(id, project_id, folder_id, title, sort_order)
+             VALUES(?, 'p1', ?, ?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM Document))",
+            params![id, folder_id, title],
+        )?;
+        tx.execute("INSERT INTO Body(document_id, markdown) VALUES(?, ?)", params![id, md])?;
+        imported.push((id, md));
+        emit_progress(&window, "import-progress", imported.len(), files.len());
+    }
+    tx.commit()?;
+
+    for (id, md) in &imported {
+        mirror_md(&conn, &project_path, id, md)?;
+    }
+    let ids: Vec<String> = imported.into_iter().map(|(id, _)| id).collect();
+    emit_tree_changed(&window, TreeChange::docs("created", ids.clone()));
+    Ok(ids)
+}
+
+#[tauri::command]
+pub fn rename_document(state: State<'_, AppState>, window: tauri::Window, project_path: String, doc_id: String, new_title: String) -> Result<(), AppError> {
+    let title = new_title.trim();
+    if title.is_empty() {
+        return Err(AppError::Validation("document title cannot be empty".into()));
+    }
+
+    let conn = state.conn_mut(&project_path)?;
+    ensure_unlocked(&conn, &doc_id)?;
+    let changed = conn.execute(
+        "UPDATE Document SET title=?, updated_at=CURRENT_TIMESTAMP WHERE id=?",
+        params![title, doc_id],
+    )?;
+    if changed == 0 {
+        return Err(AppError::NotFound("document does not exist".into()));
+    }
+
+    // The mirror's frontmatter carries the title.
+    let md: String = conn.query_row("SELECT markdown FROM Body WHERE document_id=?", [&doc_id], |r| r.get(0))?;
+    mirror_md(&conn, &project_path, &doc_id, &md)?;
+    emit_tree_changed(&window, TreeChange::docs("renamed", vec![doc_id]));
+    Ok(())
+}
+
+/// Lock or unlock a document. A locked document refuses saves, renames,
+/// snapshot restores and deletes until it's unlocked.
+#[tauri::command]
+pub fn set_document_locked(state: State<'_, AppState>, project_path: String, doc_id: String, locked: bool) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    let changed = conn.execute("UPDATE Document SET locked=? WHERE id=?", params![locked, doc_id])?;
+    if changed == 0 {
+        return Err(AppError::NotFound("document does not exist".into()));
+    }
+    Ok(())
+}
+
+/// Star or unstar a document; pinned ones come back from list_pinned.
+#[tauri::command]
+pub fn set_document_pinned(state: State<'_, AppState>, project_path: String, doc_id: String, pinned: bool) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    let changed = conn.execute("UPDATE Document SET pinned=? WHERE id=?", params![pinned, doc_id])?;
+    if changed == 0 {
+        return Err(AppError::NotFound("document does not exist".into()));
+    }
+    Ok(())
+}
+
+/// Live pinned documents, in tree order.
+#[tauri::command]
+pub fn list_pinned(state: State<'_, AppState>, project_path: String) -> Result<Vec<Doc>, AppError> {
+    let conn = state.conn(&project_path)?;
+    Ok(select_pinned_docs(&conn)?)
+}
+
+const DOCUMENT_STATUSES: &[&str] = &["draft", "revising", "done"];
+
+/// Label a document "draft", "revising" or "done" for the tree; `None` clears it.
+#[tauri::command]
+pub fn set_document_status(state: State<'_, AppState>, project_path: String, doc_id: String, status: Option<String>) -> Result<(), AppError> {
+    if let Some(s) = status.as_deref() {
+        if !DOCUMENT_STATUSES.contains(&s) {
+            return Err(AppError::Validation(format!("unknown status {s:?}; expected draft, revising or done")));
+        }
+    }
+    let conn = state.conn_mut(&project_path)?;
+    let changed = conn.execute("UPDATE Document SET status=? WHERE id=?", params![status, doc_id])?;
+    if changed == 0 {
+        return Err(AppError::NotFound("document does not exist".into()));
+    }
+    Ok(())
+}
+
+/// Deep-copy a folder subtree under `new_parent_id`: every live descendant
+/// folder, document (body + md mirror) and character (assets included) gets a
+/// fresh id. Rows go in one transaction; files are written once it commits.
+/// Returns the new root folder id.
+#[tauri::command]
+pub fn duplicate_folder(state: State<'_, AppState>, window: tauri::Window, project_path: String, folder_id: String, new_parent_id: Option<String>) -> Result<String, AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+    ensure_folder_exists(&conn, Some(&folder_id))?;
+    ensure_folder_exists(&conn, new_parent_id.as_deref())?;
+
+    let chars_root = Path::new(&project_path).join("assets").join("characters");
+    let mut md_files: Vec<(String, String)> = Vec::new();
+    let mut asset_dirs: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    let tx = conn.transaction()?;
+    // old folder id -> new folder id; BFS order means a parent is always mapped first.
+    let mut mapped: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    for old in folder_subtree(&tx, &folder_id)? {
+        let (name, parent, deleted): (String, Option<String>, Option<String>) = tx.query_row(
+            "SELECT name, parent_id, deleted_at FROM Folder WHERE id=?",
+            [&old],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )?;
+        let is_root = old == folder_id;
+        let new_parent = if is_root {
+            new_parent_id.clone()
+        } else {
+            // Children of a skipped (trashed) folder are skipped with it.
+            match parent.as_ref().and_then(|p| mapped.get(p)) {
+                Some(p) => Some(p.clone()),
+                None => continue,
+            }
+        };
+        if deleted.is_some() {
+            continue;
+        }
+
+        let new = new_id();
+        let new_name = if is_root { format!("{name} (copy)") } else { name };
+        tx.execute(
+            "INSERT INTO Folder(id, project_id, parent_id, name) VALUES(?, 'p1', ?, ?)",
+            params![new, new_parent, new_name],
+        )?;
+
+        let docs: Vec<(String, String)> = {
+            let mut st = tx.prepare(
+                "SELECT Document.id, Body.markdown
+                 FROM Document JOIN Body ON Body.document_id = Document.id
+                 WHERE Document.folder_id=? AND Document.deleted_at IS NULL",
+            )?;
+            let rows = st.query_map([&old], |r| Ok((r.get(0)?, r.get(1)?)))?;
+            rows.filter_map(Result::ok).collect()
+        };
+        for (doc_id, md) in docs {
+            let id = new_id();
+            tx.execute(
+                "INSERT INTO Document(id, project_id, folder_id, title, sort_order)
+                 SELECT ?, project_id, ?, title, sort_order FROM Document WHERE id=?",
+                params![id, new, doc_id],
+            )?;
+            tx.execute("INSERT INTO Body(document_id, markdown) VALUES(?, ?)", params![id, md])?;
+            md_files.push((id, md));
+        }
+
+        let chars: Vec<String> = {
+            let mut st = tx.prepare("SELECT id FROM Character WHERE folder_id=? AND deleted_at IS NULL")?;
+            let rows = st.query_map([&old], |r| r.get::<_, String>(0))?;
+            rows.filter_map(Result::ok).collect()
+        };
+        for char_id in chars {
+            let id = new_id();
+            tx.execute(
+                "INSERT INTO Character(id, project_id, folder_id, name, age, age_years, nationality, sexuality, height, attributes, image_path, thumbnail_path)
+                 SELECT ?, project_id, ?, name, age, age_years, nationality, sexuality, height, attributes, image_path, thumbnail_path
+                 FROM Character WHERE id=?",
+                params![id, new, char_id],
+            )?;
+            // The image paths point into the old character's asset dir; repoint them at the copy.
+            for column in ["image_path", "thumbnail_path"] {
+                let image: Option<String> =
+                    tx.query_row(&format!("SELECT {column} FROM Character WHERE id=?"), [&id], |r| r.get(0))?;
+                let Some(image) = image.filter(|i| !i.is_empty()) else { continue };
+                let img = Path::new(&image);
+                if img.parent().and_then(|p| p.file_name()) == Some(std::ffi::OsStr::new(&char_id)) {
+                    if let Some(file) = img.file_name() {
+                        let moved = chars_root.join(&id).join(file);
+                        tx.execute(
+                            &format!("UPDATE Character SET {column}=? WHERE id=?"),
+                            params![moved.to_string_lossy(), id],
+                        )?;
+                    }
+                }
+            }
+            // Gallery names are relative to the asset dir, which is copied whole.
+            let images: Vec<(String, String)> = {
+                let mut st = tx.prepare("SELECT file_name, thumbnail_name FROM CharacterImage WHERE character_id=?")?;
+                let rows = st.query_map([&char_id], |r| Ok((r.get(0)?, r.get(1)?)))?;
+                rows.filter_map(Result::ok).collect()
+            };
+            for (file_name, thumbnail_name) in images {
+                tx.execute(
+                    "INSERT INTO CharacterImage(id, character_id, file_name, thumbnail_name) VALUES(?,?,?,?)",
+                    params![new_id(), id, file_name, thumbnail_name],
+                )?;
+            }
+            asset_dirs.push((chars_root.join(&char_id), chars_root.join(&id)));
+        }
+
+        mapped.insert(old, new);
+    }
+    let new_root = mapped.get(&folder_id).cloned().unwrap_or_default();
+    tx.commit()?;
+
+    for (id, md) in &md_files {
+        mirror_md(&conn, &project_path, id, md)?;
+    }
+    for (from, to) in &asset_dirs {
+        if !from.is_dir() {
+            continue;
+        }
+        for entry in walkdir::WalkDir::new(from).into_iter().flatten().filter(|e| e.file_type().is_file()) {
+            let rel = entry.path().strip_prefix(from).unwrap();
+            let dest = to.join(rel);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    emit_tree_changed(&window, TreeChange::folders("created", vec![new_root.clone()]));
+    Ok(new_root)
+}
+
+#[tauri::command]
+pub fn create_folder(state: State<'_, AppState>, window: tauri::Window, project_path: String, name: String, parent_id: Option<String>) -> Result<Folder, AppError> {
+    let conn = state.conn_mut(&project_path)?;
+
+    let id = new_id();
+    conn.execute(
+        "INSERT INTO Folder(id, project_id, parent_id, name) VALUES(?, 'p1', ?, ?)",
+        params![id, parent_id, name],
+    )?;
+
+    emit_tree_changed(&window, TreeChange::folders("created", vec![id.clone()]));
+    Ok(Folder { id, name, parent_id, doc_count: None, word_count: None })
+}
+
+#[tauri::command]
+pub fn move_document(state: State<'_, AppState>, window: tauri::Window, project_path: String, doc_id: String, new_folder_id: Option<String>) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+
+    ensure_folder_exists(&conn, new_folder_id.as_deref())?;
+
+    let changed = conn.execute(
+        "UPDATE Document SET folder_id=?, updated_at=CURRENT_TIMESTAMP WHERE id=?",
+        params![new_folder_id, doc_id],
+    )?;
+    if changed == 0 {
+        return Err(AppError::NotFound("document does not exist".into()));
+    }
+    remirror_docs(&conn, &project_path, std::slice::from_ref(&doc_id))?;
+    emit_tree_changed(&window, TreeChange::docs("moved", vec![doc_id]));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn move_folder(state: State<'_, AppState>, window: tauri::Window, project_path: String, folder_id: String, new_parent_id: Option<String>) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    reparent_folder(&conn, &folder_id, new_parent_id.as_deref())?;
+
+    let subtree = folder_subtree(&conn, &folder_id)?;
+    let doc_ids: Vec<String> = {
+        let mut st = conn.prepare(&format!(
+            "SELECT id FROM Document WHERE folder_id IN ({})",
+            vec!["?"; subtree.len()].join(",")
+        ))?;
+        let rows = st.query_map(params_from_iter(&subtree), |r| r.get(0))?;
+        rows.filter_map(Result::ok).collect()
+    };
+    remirror_docs(&conn, &project_path, &doc_ids)?;
+    emit_tree_changed(&window, TreeChange::folders("moved", vec![folder_id]));
+    Ok(())
+}
+
+// The row change behind move_folder, refusing a cycle or a missing folder.
+fn reparent_folder(conn: &Connection, folder_id: &str, new_parent_id: Option<&str>) -> Result<(), AppError> {
+    ensure_folder_exists(conn, new_parent_id)?;
+
+    // Walk up from the new parent to the root; hitting `folder_id` means the
+    // target is inside the folder being moved, which would create a cycle.
+    let mut cursor = new_parent_id.map(str::to_string);
+    let mut seen = std::collections::HashSet::new();
+    while let Some(current) = cursor {
+        if current == folder_id {
+            return Err(AppError::Validation("cannot move a folder into its own subtree".into()));
+        }
+        if !seen.insert(current.clone()) {
+            break; // already-corrupt hierarchy; don't spin forever
+        }
+        let parent: Option<Option<String>> = conn
+            .query_row("SELECT parent_id FROM Folder WHERE id=?", [&current], |r| r.get(0))
+            .optional()?;
+        match parent {
+            Some(p) => cursor = p,
+            None => return Err(AppError::NotFound("target folder does not exist".into())),
+        }
+    }
+
+    let changed = conn.execute(
+        "UPDATE Folder SET parent_id=? WHERE id=?",
+        params![new_parent_id, folder_id],
+    )?;
+    if changed == 0 {
+        return Err(AppError::NotFound("folder does not exist".into()));
+    }
+    Ok(())
+}
+
+/// Reparent several documents and characters under `target_folder_id` (the
+/// root if `None`) in one transaction; an unknown id moves nothing. Folders
+/// go through `move_folder`, which guards against cycles.
+#[tauri::command]
+pub fn move_items(
+    state: State<'_, AppState>,
+    window: tauri::Window,
+    project_path: String,
+    doc_ids: Vec<String>,
+    char_ids: Vec<String>,
+    target_folder_id: Option<String>,
+) -> Result<(), AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+    ensure_folder_exists(&conn, target_folder_id.as_deref())?;
+
+    let tx = conn.transaction()?;
+    for doc_id in &doc_ids {
+        let changed = tx.execute(
+            "UPDATE Document SET folder_id=?, updated_at=CURRENT_TIMESTAMP WHERE id=?",
+            params![target_folder_id, doc_id],
+        )?;
+        if changed == 0 {
+            return Err(AppError::NotFound(format!("document {doc_id} does not exist")));
+        }
+    }
+    for char_id in &char_ids {
+        let changed = tx.execute(
+            "UPDATE Character SET folder_id=?, updated_at=CURRENT_TIMESTAMP WHERE id=?",
+            params![target_folder_id, char_id],
+        )?;
+        if changed == 0 {
+            return Err(AppError::NotFound(format!("character {char_id} does not exist")));
+        }
+    }
+    tx.commit()?;
+
+    remirror_docs(&conn, &project_path, &doc_ids)?;
+    emit_tree_changed(&window, TreeChange { kind: "moved", doc_ids, folder_ids: Vec::new(), character_ids: char_ids });
+    Ok(())
+}
+
+// Give the listed docs sort_order 1..n; ids outside `folder_id` are ignored.
+#[tauri::command]
+pub fn reorder_documents(state: State<'_, AppState>, window: tauri::Window, project_path: String, folder_id: Option<String>, ordered_ids: Vec<String>) -> Result<(), AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+
+    let tx = conn.transaction()?;
+    for (i, doc_id) in ordered_ids.iter().enumerate() {
+        tx.execute(
+            "UPDATE Document SET sort_order=? WHERE id=? AND folder_id IS ?",
+            params![i as i64 + 1, doc_id, folder_id],
+        )?;
+    }
+    tx.commit()?;
+    emit_tree_changed(&window, TreeChange::docs("reordered", ordered_ids));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn load_document(state: State<'_, AppState>, project_path: String, doc_id: String) -> Result<String, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let mut st = conn.prepare("SELECT markdown FROM Body WHERE document_id=?")?;
+    let md: String = st.query_row([doc_id], |r| r.get(0))?;
+    Ok(md)
+}
+
+/// A slice of a document body for progressive loading. `offset`/`length` count
+/// characters (not bytes) and are clamped to the body; `total` is the full
+/// length, so the caller is done once `offset + text length >= total`.
+#[tauri::command]
+pub fn load_document_range(state: State<'_, AppState>, project_path: String, doc_id: String, offset: u64, length: u64) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    // substr/length work on characters in SQLite, so the slice never splits a
+    // UTF-8 sequence and the full body never has to cross into Rust.
+    let total: i64 = conn.query_row("SELECT length(markdown) FROM Body WHERE document_id=?", [&doc_id], |r| r.get(0))?;
+    let offset = i64::try_from(offset).unwrap_or(i64::MAX).min(total);
+    let length = i64::try_from(length).unwrap_or(i64::MAX).min(total - offset);
+    let text: String = conn.query_row(
+        "SELECT substr(markdown, ?, ?) FROM Body WHERE document_id=?",
+        params![offset + 1, length, doc_id],
+        |r| r.get(0),
+    )?;
+
+    Ok(serde_json::json!({ "text": text, "offset": offset, "total": total }))
+}
+
+/// Header info for a document without shipping its body over IPC.
+/// `updatedAt` follows the same rule as `list_tree`.
+#[tauri::command]
+pub fn get_document(state: State<'_, AppState>, project_path: String, doc_id: String) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let (mut doc, md) = conn
+        .query_row(
+            "SELECT d.id, d.title, d.folder_id, d.created_at,
+                    MAX(d.updated_at, COALESCE(b.updated_at, d.updated_at)), b.markdown, d.locked, d.status, d.pinned
+             FROM Document d
+             LEFT JOIN Body b ON b.document_id = d.id
+             WHERE d.id=?",
+            [&doc_id],
+            |r| {
+                Ok((
+                    serde_json::json!({
+                        "id": r.get::<_, String>(0)?,
+                        "title": r.get::<_, String>(1)?,
+                        "folderId": r.get::<_, Option<String>>(2)?,
+                        "createdAt": r.get::<_, Option<String>>(3)?,
+                        "updatedAt": r.get::<_, Option<String>>(4)?,
+                        "locked": r.get::<_, bool>(6)?,
+                        "status": r.get::<_, Option<String>>(7)?,
+                        "pinned": r.get::<_, bool>(8)?,
+                    }),
+                    r.get::<_, Option<String>>(5)?.unwrap_or_default(),
+                ))
+            },
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("document does not exist".into()))?;
+
+    doc["wordCount"] = serde_json::json!(text_stats(&md).0);
+    Ok(doc)
+}
+
+/// `expected_updated_at` is the Body `updated_at` the caller last saw, in
+/// SQLite's `CURRENT_TIMESTAMP` form (`YYYY-MM-DD HH:MM:SS`, UTC). When given
+/// and the stored value differs, the save is refused with a conflict instead of
+/// overwriting whatever changed the body in between. Returns the new
+/// `updated_at`, to pass as `expected_updated_at` on the next save.
+#[tauri::command]
+pub fn save_document(
+    state: State<'_, AppState>,
+    project_path: String,
+    doc_id: String,
+    markdown: String,
+    expected_updated_at: Option<String>,
+) -> Result<String, AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    ensure_unlocked(&conn, &doc_id)?;
+
+    // Check and write in one statement so nothing can slip in between. Saving
+    // identical text isn't an edit, so updated_at (and with it the mirror's
+    // frontmatter) stays put.
+    let updated_at: Option<String> = conn
+        .query_row(
+            "UPDATE Body SET markdown=?1,
+                    updated_at=CASE WHEN markdown IS ?1 THEN updated_at ELSE CURRENT_TIMESTAMP END
+             WHERE document_id=?2 AND (?3 IS NULL OR updated_at=?3)
+             RETURNING updated_at",
+            params![markdown, doc_id, expected_updated_at],
+            |r| r.get(0),
+        )
+        .optional()?;
+    let Some(updated_at) = updated_at else {
+        let exists: i64 = conn.query_row("SELECT COUNT(*) FROM Body WHERE document_id=?", [&doc_id], |r| r.get(0))?;
+        return Err(if exists == 0 {
+            AppError::NotFound("document does not exist".into())
+        } else {
+            AppError::Conflict("document changed since last load".into())
+        });
+    };
+
+    maybe_autosnapshot(&conn, &doc_id, &markdown)?;
+    mirror_md(&conn, &project_path, &doc_id, &markdown)?;
+    if let Some((session_id, words_added)) = state.track_session_words(&project_path, &doc_id, || text_stats(&markdown).0) {
+        conn.execute("UPDATE WritingSession SET words_added=? WHERE id=?", params![words_added, session_id])?;
+    }
+    Ok(updated_at)
+}
+
+// Snapshot `markdown` when it differs from the doc's latest snapshot (or from
+// nothing, if it has none) by more than the `autosnapshot_threshold` setting,
+// counted in inserted + deleted characters. A missing or 0 threshold disables it.
+fn maybe_autosnapshot(conn: &Connection, doc_id: &str, markdown: &str) -> Result<(), AppError> {
+    let threshold: usize = conn
+        .query_row("SELECT value FROM Settings WHERE key='autosnapshot_threshold'", [], |r| r.get::<_, Option<String>>(0))
+        .optional()?
+        .flatten()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0);
+    if threshold == 0 {
+        return Ok(());
+    }
+
+    let last: String = conn
+        .query_row(
+            "SELECT markdown FROM Snapshot WHERE document_id=? ORDER BY created_at DESC, rowid DESC LIMIT 1",
+            [doc_id],
+            |r| r.get::<_, Option<String>>(0),
+        )
+        .optional()?
+        .flatten()
+        .unwrap_or_default();
+
+    // Word granularity: a line diff would count a one-word edit to a long
+    // paragraph as the whole paragraph changing.
+    let changed: usize = similar::TextDiff::from_words(last.as_str(), markdown)
+        .iter_all_changes()
+        .filter(|c| c.tag() != similar::ChangeTag::Equal)
+        .map(|c| c.value().chars().count())
+        .sum();
+    if changed > threshold {
+        let note = generated_snapshot_note(conn, doc_id, markdown, "auto-snapshot")?;
+        conn.execute(
+            "INSERT INTO Snapshot(id, document_id, note, markdown) VALUES(?,?,?,?)",
+            params![new_id(), doc_id, note, markdown],
+        )?;
+    }
+    Ok(())
+}
+
+// `words` as a percentage of `target` to one decimal place; past 100 once the
+// goal is beaten, None without a goal.
+fn percent_complete(words: usize, target: Option<i64>) -> Option<f64> {
+    let target = target.filter(|t| *t > 0)?;
+    Some((words as f64 * 1000.0 / target as f64).round() / 10.0)
+}
+
+fn check_target_words(target: Option<i64>) -> Result<(), AppError> {
+    if target.is_some_and(|t| t <= 0) {
+        return Err(AppError::Validation("target word count must be positive".into()));
+    }
+    Ok(())
+}
+
+/// `targetWords` and `percentComplete` are null unless the document has a
+/// goal set via `set_document_target_words`.
+#[tauri::command]
+pub fn document_stats(state: State<'_, AppState>, project_path: String, doc_id: String) -> Result<serde_json::Value, AppError> {
+    let target: Option<i64> = state
+        .conn(&project_path)?
+        .query_row("SELECT target_words FROM Document WHERE id=?", [&doc_id], |r| r.get(0))
+        .optional()?
+        .flatten();
+    let md = load_document(state, project_path, doc_id)?;
+    let (words, chars, no_spaces) = text_stats(&md);
+    Ok(serde_json::json!({
+        "words": words,
+        "chars": chars,
+        "charsNoSpaces": no_spaces,
+        "targetWords": target,
+        "percentComplete": percent_complete(words, target),
+    }))
+}
+
+/// Set or clear (`None`) a document's word-count goal.
+#[tauri::command]
+pub fn set_document_target_words(state: State<'_, AppState>, project_path: String, doc_id: String, target: Option<i64>) -> Result<(), AppError> {
+    check_target_words(target)?;
+    let conn = state.conn_mut(&project_path)?;
+    let changed = conn.execute("UPDATE Document SET target_words=? WHERE id=?", params![target, doc_id])?;
+    if changed == 0 {
+        return Err(AppError::NotFound("document does not exist".into()));
+    }
+    Ok(())
+}
+
+/// Set or clear (`None`) the whole project's word-count goal, stored as the
+/// `target_words` setting.
+#[tauri::command]
+pub fn set_project_target_words(state: State<'_, AppState>, project_path: String, target: Option<i64>) -> Result<(), AppError> {
+    check_target_words(target)?;
+    let conn = state.conn_mut(&project_path)?;
+    match target {
+        Some(t) => conn.execute(
+            "INSERT INTO Settings(key, value) VALUES('target_words', ?)
+             ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+            [t.to_string()],
+        )?,
+        None => conn.execute("DELETE FROM Settings WHERE key='target_words'", [])?,
+    };
+    Ok(())
+}
+
+#[tauri::command]
+pub fn project_stats(state: State<'_, AppState>, project_path: String) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    // One pass over every body; docs at the project root have no folder key.
+    let mut st = conn.prepare(
+        "SELECT Document.folder_id, Body.markdown
+         FROM Body JOIN Document ON Body.document_id = Document.id
+         WHERE Document.deleted_at IS NULL",
+    )?;
+    let rows = st.query_map([], |r| Ok((r.get::<_, Option<String>>(0)?, r.get::<_, String>(1)?)))?;
+
+    let mut total = 0usize;
+    let mut root = 0usize;
+    let mut by_folder: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    for (folder_id, md) in rows.filter_map(|r| r.ok()) {
+        let (words, _, _) = text_stats(&md);
+        total += words;
+        match folder_id {
+            Some(fid) => *by_folder.entry(fid).or_default() += words,
+            None => root += words,
+        }
+    }
+    let target: Option<i64> = conn
+        .query_row("SELECT value FROM Settings WHERE key='target_words'", [], |r| r.get::<_, Option<String>>(0))
+        .optional()?
+        .flatten()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|t| *t > 0);
+    Ok(serde_json::json!({
+        "totalWords": total,
+        "rootWords": root,
+        "folders": by_folder,
+        "targetWords": target,
+        "percentComplete": percent_complete(total, target),
+    }))
+}
+
+/// Counts and sizes for a project properties panel. Trashed rows aren't
+/// counted; `lastModified` is the newest document or body `updated_at`.
+#[tauri::command]
+pub fn project_info(state: State<'_, AppState>, project_path: String) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+    let count = |sql: &str| conn.query_row(sql, [], |r| r.get::<_, i64>(0));
+    let documents = count("SELECT COUNT(*) FROM Document WHERE deleted_at IS NULL")?;
+    let folders = count("SELECT COUNT(*) FROM Folder WHERE deleted_at IS NULL")?;
+    let characters = count("SELECT COUNT(*) FROM Character WHERE deleted_at IS NULL")?;
+    let snapshots = count("SELECT COUNT(*) FROM Snapshot")?;
+    let last_modified: Option<String> = conn.query_row(
+        "SELECT MAX(t) FROM (SELECT MAX(updated_at) AS t FROM Document UNION ALL SELECT MAX(updated_at) FROM Body)",
+        [],
+        |r| r.get(0),
+    )?;
+
+    let root = Path::new(&project_path);
+    let md_bytes: u64 = walkdir::WalkDir::new(root.join("md"))
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum();
+    let db_bytes = fs::metadata(root.join("project.db"))?.len();
+
+    Ok(serde_json::json!({
+        "documents": documents,
+        "folders": folders,
+        "characters": characters,
+        "snapshots": snapshots,
+        "mdBytes": md_bytes,
+        "dbBytes": db_bytes,
+        "lastModified": last_modified,
+    }))
+}
+
+const SEARCH_DEFAULT_LIMIT: u32 = 50;
+const SEARCH_MAX_LIMIT: u32 = 500;
+const SNIPPET_DEFAULT_TOKENS: u32 = 12;
+// FTS5's own ceiling for snippet().
+const SNIPPET_MAX_TOKENS: u32 = 64;
+
+/// FTS5 search over document bodies. Matching ignores case and accents
+/// ("cafe" finds "Café"); projects indexed before that was the case pick it
+/// up when open_project migrates them. Snippets carry `snippet_tokens` tokens
+/// of context (default 12) with matches wrapped in `highlight_open` /
+/// `highlight_close` (default `<b>` / `</b>`). Trashed documents are left out
+/// unless `include_trashed` is set, e.g. to find something to restore.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn search(
+    state: State<'_, AppState>,
+    project_path: String,
+    q: String,
+    folder_id: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    snippet_tokens: Option<u32>,
+    highlight_open: Option<String>,
+    highlight_close: Option<String>,
+    include_trashed: Option<bool>,
+) -> Result<SearchPage, AppError> {
+    let tokens = snippet_tokens.unwrap_or(SNIPPET_DEFAULT_TOKENS);
+    if !(1..=SNIPPET_MAX_TOKENS).contains(&tokens) {
+        return Err(AppError::Validation(format!("snippet_tokens must be between 1 and {SNIPPET_MAX_TOKENS}")));
+    }
+    let open = highlight_open.unwrap_or_else(|| "<b>".into());
+    let close = highlight_close.unwrap_or_else(|| "</b>".into());
+
+    let conn = state.conn(&project_path)?;
+    let limit = limit.unwrap_or(SEARCH_DEFAULT_LIMIT).min(SEARCH_MAX_LIMIT);
+    let offset = offset.unwrap_or(0);
+
+    // Scoping to a folder covers its whole subtree, e.g. every chapter of one book.
+    let scope = match &folder_id {
+        Some(fid) => folder_subtree(&conn, fid)?,
+        None => Vec::new(),
+    };
+    let scope_sql = if scope.is_empty() {
+        String::new()
+    } else {
+        format!("AND Document.folder_id IN ({})", vec!["?"; scope.len()].join(","))
+    };
+    // The index covers trashed bodies too, so filter them out on Document.
+    let trash_sql = if include_trashed.unwrap_or(false) { "" } else { "AND Document.deleted_at IS NULL" };
+    let from_sql = format!(
+        "FROM body_fts
+         JOIN Body ON body_fts.rowid = Body.rowid
+         JOIN Document ON Body.document_id = Document.id
+         WHERE body_fts MATCH ? {trash_sql} {scope_sql}"
+    );
+    let args: Vec<String> = std::iter::once(q).chain(scope).collect();
+
+    let total: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) {from_sql}"),
+        params_from_iter(&args),
+        |r| r.get(0),
+    )?;
+
+    // limit/offset/tokens are plain integers, so they're safe to inline; the
+    // highlight markup is bound, ahead of the MATCH arguments.
+    let mut st = conn.prepare(&format!(
+        "SELECT Document.id, Document.title, snippet(body_fts, -1, ?, ?, '…', {tokens})
+         {from_sql}
+         LIMIT {limit} OFFSET {offset}"
+    ))?;
+    let hit_args = [open, close].into_iter().chain(args);
+    let rows = st.query_map(params_from_iter(hit_args), |row| {
+        Ok(SearchHit { id: row.get(0)?, title: row.get(1)?, snippet: row.get(2)? })
+    })?;
+    let hits = rows.filter_map(|r| r.ok()).collect();
+
+    Ok(SearchPage { hits, total })
+}
+
+// Characters of context shown either side of a regex match.
+const REGEX_CONTEXT_CHARS: usize = 40;
+// Cap on the compiled program. The regex crate matches in linear time, so
+// the size of the compiled pattern is the only thing left to bound.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+// `text` around byte range `start..end`, the match wrapped in <b></b> like the
+// FTS snippets and cut ends marked with `…`.
+fn match_snippet(text: &str, start: usize, end: usize) -> String {
+    let before: String = {
+        let mut chars: Vec<char> = text[..start].chars().rev().take(REGEX_CONTEXT_CHARS).collect();
+        chars.reverse();
+        chars.into_iter().collect()
+    };
+    let after: String = text[end..].chars().take(REGEX_CONTEXT_CHARS).collect();
+    let lead = if before.len() < start { "…" } else { "" };
+    let trail = if after.len() < text.len() - end { "…" } else { "" };
+    format!("{lead}{before}<b>{}</b>{after}{trail}", &text[start..end])
+}
+
+/// Regular-expression search over live document bodies, one hit per match.
+/// `fts_filter`, an FTS MATCH query, narrows the candidates first on large
+/// projects. Paged like `search`; `total` counts matches, not documents.
+/// Empty matches (what `x*` or `^` find between characters) don't count.
+#[tauri::command]
+pub fn search_regex(
+    state: State<'_, AppState>,
+    project_path: String,
+    pattern: String,
+    fts_filter: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+) -> Result<SearchPage, AppError> {
+    let re = regex::RegexBuilder::new(&pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| AppError::Validation(format!("invalid pattern: {e}")))?;
+    let limit = limit.unwrap_or(SEARCH_DEFAULT_LIMIT).min(SEARCH_MAX_LIMIT) as usize;
+    let offset = offset.unwrap_or(0) as usize;
+
+    let conn = state.conn(&project_path)?;
+    let filter = fts_filter.filter(|f| !f.trim().is_empty());
+    let sql = format!(
+        "SELECT Document.id, Document.title, Body.markdown
+         FROM Body JOIN Document ON Body.document_id = Document.id
+         WHERE Document.deleted_at IS NULL {}
+         ORDER BY Document.sort_order, Document.created_at",
+        if filter.is_some() { "AND Body.rowid IN (SELECT rowid FROM body_fts WHERE body_fts MATCH ?)" } else { "" }
+    );
+    let mut st = conn.prepare(&sql)?;
+    let rows = st.query_map(params_from_iter(filter.iter()), |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, Option<String>>(2)?.unwrap_or_default()))
+    })?;
+
+    let mut hits = Vec::new();
+    let mut total = 0;
+    for (id, title, md) in rows.filter_map(Result::ok) {
+        for m in regex_matches(&re, &md) {
+            if total >= offset && hits.len() < limit {
+                hits.push(SearchHit { id: id.clone(), title: title.clone(), snippet: match_snippet(&md, m.start(), m.end()) });
+            }
+            total += 1;
+        }
+    }
+
+    Ok(SearchPage { hits, total: total as i64 })
+}
+
+// A pattern that can match nothing would otherwise hit at every position.
+fn regex_matches<'a>(re: &'a regex::Regex, md: &'a str) -> impl Iterator<Item = regex::Match<'a>> + 'a {
+    re.find_iter(md).filter(|m| !m.is_empty())
+}
+
+// Escape LIKE wildcards so a literal `%` or `_` in the query matches itself.
+fn like_pattern(q: &str) -> String {
+    let escaped = q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+/// Substring match on character name, nationality and the attributes JSON.
+#[tauri::command]
+pub fn search_characters(state: State<'_, AppState>, project_path: String, q: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let conn = state.conn(&project_path)?;
+    find_characters(&conn, &q)
+}
+
+// Live characters only, like list_characters; trashed ones stay out of search.
+fn find_characters(conn: &Connection, q: &str) -> Result<Vec<serde_json::Value>, AppError> {
+    let mut st = conn.prepare(
+        "SELECT id, name,
+                CASE WHEN name LIKE ?1 ESCAPE '\\' THEN 'name'
+                     WHEN nationality LIKE ?1 ESCAPE '\\' THEN 'nationality'
+                     ELSE 'attributes' END
+         FROM Character
+         WHERE deleted_at IS NULL
+           AND (name LIKE ?1 ESCAPE '\\'
+                OR nationality LIKE ?1 ESCAPE '\\'
+                OR attributes LIKE ?1 ESCAPE '\\')
+         ORDER BY name ASC",
+    )?;
+    let rows = st.query_map([like_pattern(q)], |r| {
+        Ok(serde_json::json!({
+            "id": r.get::<_, String>(0)?,
+            "name": r.get::<_, String>(1)?,
+            "matchedField": r.get::<_, String>(2)?,
+        }))
+    })?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Cross-check `md/` against the Document table: `orphanedFiles` are md files
+/// with no document, `missingFiles` are documents with no md file, both as
+/// paths relative to `md/`. With `repair`, orphans are deleted and missing
+/// mirrors rewritten from Body.
+#[tauri::command]
+pub fn audit_project(state: State<'_, AppState>, project_path: String, repair: Option<bool>) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+    let md_dir = Path::new(&project_path).join("md");
+
+    // Trashed docs keep their mirror, so they count as known.
+    let expected: std::collections::HashMap<String, String> = {
+        let mut st = conn.prepare(
+            "SELECT Document.id, MirrorPath.path
+             FROM Document LEFT JOIN MirrorPath ON MirrorPath.document_id = Document.id",
+        )?;
+        let rows = st.query_map([], |r| {
+            let id: String = r.get(0)?;
+            let path: Option<String> = r.get(1)?;
+            Ok((path.unwrap_or_else(|| format!("{id}.md")), id))
+        })?;
+        rows.filter_map(Result::ok).collect()
+    };
+    let files: std::collections::HashSet<String> = walkdir::WalkDir::new(&md_dir)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|x| x == "md"))
+        .filter_map(|e| e.path().strip_prefix(&md_dir).ok().map(|p| p.to_string_lossy().replace('\\', "/")))
+        .collect();
+
+    let mut orphaned: Vec<String> = files.iter().filter(|f| !expected.contains_key(*f)).cloned().collect();
+    let mut missing: Vec<String> = expected.keys().filter(|p| !files.contains(*p)).cloned().collect();
+    orphaned.sort();
+    missing.sort();
+
+    if repair.unwrap_or(false) {
+        state.ensure_writable(&project_path)?;
+        let orphan_paths: Vec<PathBuf> = orphaned.iter().map(|rel| md_dir.join(rel)).collect();
+        remove_paths(&project_path, &orphan_paths);
+        for rel in &missing {
+            let id = &expected[rel];
+            let md: Option<String> = conn
+                .query_row("SELECT markdown FROM Body WHERE document_id=?", [id], |r| r.get(0))
+                .optional()?;
+            mirror_md(&conn, &project_path, id, &md.unwrap_or_default())?;
+        }
+    }
+
+    Ok(serde_json::json!({ "orphanedFiles": orphaned, "missingFiles": missing }))
+}
+
+/// Move every mirror to where the current `mirror_layout` setting puts it;
+/// returns how many files moved. Run after changing the setting; until then
+/// each mirror moves the next time its document is saved.
+#[tauri::command]
+pub fn relayout_mirrors(state: State<'_, AppState>, project_path: String) -> Result<usize, AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    let docs: Vec<(String, Option<String>)> = {
+        let mut st = conn.prepare(
+            "SELECT Document.id, Body.markdown
+             FROM Document LEFT JOIN Body ON Body.document_id = Document.id
+             ORDER BY Document.created_at",
+        )?;
+        let rows = st.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut moved = 0;
+    for (id, md) in docs {
+        let before = mirror_path(&conn, &project_path, &id)?;
+        mirror_md(&conn, &project_path, &id, &md.unwrap_or_default())?;
+        if mirror_path(&conn, &project_path, &id)? != before {
+            moved += 1;
+        }
+    }
+    Ok(moved)
+}
+
+// Re-derive body_fts from Body, for when the index has drifted out of sync.
+#[tauri::command]
+pub fn rebuild_search_index(state: State<'_, AppState>, project_path: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    conn.execute("INSERT INTO body_fts(body_fts) VALUES('rebuild')", [])?;
+    Ok(())
+}
+
+// Byte length of the prefix of `hay` that matches `needle` (already
+// lowercased) ignoring case, if it does.
+fn match_ignore_case(hay: &str, needle: &[char]) -> Option<usize> {
+    let mut k = 0;
+    for (off, c) in hay.char_indices() {
+        if k == needle.len() {
+            return Some(off);
+        }
+        for lc in c.to_lowercase() {
+            if needle.get(k) != Some(&lc) {
+                return None;
+            }
+            k += 1;
+        }
+    }
+    (k == needle.len()).then_some(hay.len())
+}
+
+fn replace_ignore_case(text: &str, find: &str, replace: &str) -> String {
+    let needle: Vec<char> = find.chars().flat_map(char::to_lowercase).collect();
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match match_ignore_case(rest, &needle) {
+            Some(n) => {
+                out.push_str(replace);
+                rest = &rest[n..];
+            }
+            None => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+/// Replace `find` with `replace` in every live, unlocked document; returns how
+/// many documents changed. Each changed document is snapshotted first so the
+/// replacement can be undone with `restore_snapshot`.
+#[tauri::command]
+pub fn replace_all(
+    state: State<'_, AppState>,
+    project_path: String,
+    find: String,
+    replace: String,
+    case_sensitive: bool,
+) -> Result<usize, AppError> {
+    if find.is_empty() {
+        return Err(AppError::Validation("search text cannot be empty".into()));
+    }
+
+    let mut conn = state.conn_mut(&project_path)?;
+    let tx = conn.transaction()?;
+    let bodies: Vec<(String, String)> = {
+        let mut st = tx.prepare(
+            "SELECT b.document_id, b.markdown
+             FROM Body b JOIN Document d ON d.id = b.document_id
+             WHERE d.deleted_at IS NULL AND d.locked = 0",
+        )?;
+        let rows = st.query_map([], |r| Ok((r.get(0)?, r.get::<_, Option<String>>(1)?.unwrap_or_default())))?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut changed = Vec::new();
+    for (doc_id, md) in bodies {
+        let new_md = if case_sensitive { md.replace(&find, &replace) } else { replace_ignore_case(&md, &find, &replace) };
+        if new_md == md {
+            continue;
+        }
+        tx.execute(
+            "INSERT INTO Snapshot(id, document_id, note, markdown) VALUES(?,?,?,?)",
+            params![new_id(), doc_id, format!("before replacing \"{find}\""), md],
+        )?;
+        tx.execute(
+            "UPDATE Body SET markdown=?, updated_at=CURRENT_TIMESTAMP WHERE document_id=?",
+            params![new_md, doc_id],
+        )?;
+        changed.push((doc_id, new_md));
+    }
+    tx.commit()?;
+
+    // Only the documents that changed get their mirror rewritten.
+    for (doc_id, md) in &changed {
+        mirror_md(&conn, &project_path, doc_id, md)?;
+    }
+    Ok(changed.len())
+}
+
+// "snapshot @ 2024-05-01 14:32 (+812 words)": `label`, the local time, and
+// the change in word count since the document's previous snapshot (or since
+// nothing, for its first).
+fn generated_snapshot_note(conn: &Connection, doc_id: &str, md: &str, label: &str) -> Result<String, AppError> {
+    let previous: Option<String> = conn
+        .query_row(
+            "SELECT markdown FROM Snapshot WHERE document_id=? ORDER BY created_at DESC, rowid DESC LIMIT 1",
+            [doc_id],
+            |r| r.get::<_, Option<String>>(0),
+        )
+        .optional()?
+        .flatten();
+    let before = previous.as_deref().map_or(0, |p| text_stats(p).0) as i64;
+    let delta = text_stats(md).0 as i64 - before;
+    let unit = if delta.abs() == 1 { "word" } else { "words" };
+    Ok(format!("{label} @ {} ({delta:+} {unit})", chrono::Local::now().format("%Y-%m-%d %H:%M")))
+}
+
+/// Snapshot a document's current body. A blank `note` gets a generated one
+/// with the time and the word delta since the previous snapshot.
+#[tauri::command]
+pub fn create_snapshot(state: State<'_, AppState>, project_path: String, doc_id: String, note: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+
+    let md: String = conn.query_row("SELECT markdown FROM Body WHERE document_id=?", [doc_id.clone()], |r| r.get(0))?;
+    let note = if note.trim().is_empty() { generated_snapshot_note(&conn, &doc_id, &md, "snapshot")? } else { note };
+
+    let id = new_id();
+    conn.execute(
+        "INSERT INTO Snapshot(id, document_id, note, markdown) VALUES(?,?,?,?)",
+        params![id, doc_id, note, md],
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_snapshots(state: State<'_, AppState>, project_path: String, doc_id: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let mut st = conn.prepare(
+        "SELECT id, note, created_at
+         FROM Snapshot WHERE document_id=?
+         ORDER BY created_at DESC, rowid DESC",
+    )?;
+    let rows = st.query_map([doc_id], |r| {
+        Ok(serde_json::json!({
+            "id": r.get::<_, String>(0)?,
+            "note": r.get::<_, Option<String>>(1)?,
+            "createdAt": r.get::<_, Option<String>>(2)?,
+        }))
+    })?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+#[tauri::command]
+pub fn restore_snapshot(state: State<'_, AppState>, project_path: String, doc_id: String, snapshot_id: String) -> Result<(), AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+    ensure_unlocked(&conn, &doc_id)?;
+
+    // Only restore snapshots that were taken of this document.
+    let snap_md: String = conn.query_row(
+        "SELECT markdown FROM Snapshot WHERE id=? AND document_id=?",
+        params![snapshot_id, doc_id],
+        |r| r.get::<_, Option<String>>(0),
+    ).optional()?
+        .ok_or_else(|| AppError::NotFound("snapshot does not belong to this document".into()))?
+        .unwrap_or_default();
+
+    let current: String = conn.query_row("SELECT markdown FROM Body WHERE document_id=?", [doc_id.clone()], |r| r.get(0))?;
+
+    // Keep the current text as a snapshot so the restore itself can be undone.
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO Snapshot(id, document_id, note, markdown) VALUES(?,?,?,?)",
+        params![new_id(), doc_id, "auto-backup before restore", current],
+    )?;
+    tx.execute(
+        "UPDATE Body SET markdown=?, updated_at=CURRENT_TIMESTAMP WHERE document_id=?",
+        params![snap_md, doc_id],
+    )?;
+    tx.commit()?;
+
+    mirror_md(&conn, &project_path, &doc_id, &snap_md)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_snapshot(state: State<'_, AppState>, project_path: String, snapshot_id: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    conn.execute("DELETE FROM Snapshot WHERE id=?", params![snapshot_id])?;
+    Ok(())
+}
+
+// Passed as `snapshot_b` to diff a snapshot against the document's live body.
+const CURRENT_BODY: &str = "current";
+
+/// Line diff from `snapshot_a` to `snapshot_b` as `{ tag, text }` segments,
+/// where tag is "equal", "insert" or "delete" and consecutive lines with the
+/// same tag are merged. Both snapshots must be of the same document;
+/// `snapshot_b = "current"` compares against that document's current body.
+#[tauri::command]
+pub fn diff_snapshots(state: State<'_, AppState>, project_path: String, snapshot_a: String, snapshot_b: String) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let load = |id: &str| -> Result<(String, String), AppError> {
+        conn.query_row(
+            "SELECT document_id, markdown FROM Snapshot WHERE id=?",
+            [id],
+            |r| Ok((r.get(0)?, r.get::<_, Option<String>>(1)?.unwrap_or_default())),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("snapshot {id} does not exist")))
+    };
+
+    let (doc_a, old) = load(&snapshot_a)?;
+    let new = if snapshot_b == CURRENT_BODY {
+        conn.query_row("SELECT markdown FROM Body WHERE document_id=?", [&doc_a], |r| r.get(0))?
+    } else {
+        let (doc_b, md) = load(&snapshot_b)?;
+        if doc_b != doc_a {
+            return Err(AppError::Validation("snapshots belong to different documents".into()));
+        }
+        md
+    };
+
+    let mut segments: Vec<(&'static str, String)> = Vec::new();
+    for change in similar::TextDiff::from_lines(&old, &new).iter_all_changes() {
+        let tag = match change.tag() {
+            similar::ChangeTag::Equal => "equal",
+            similar::ChangeTag::Insert => "insert",
+            similar::ChangeTag::Delete => "delete",
+        };
+        match segments.last_mut() {
+            Some((last, text)) if *last == tag => text.push_str(change.value()),
+            _ => segments.push((tag, change.value().to_string())),
+        }
+    }
+
+    Ok(serde_json::Value::Array(
+        segments
+            .into_iter()
+            .map(|(tag, text)| serde_json::json!({ "tag": tag, "text": text }))
+            .collect(),
+    ))
+}
+
+// Keep the `keep` newest snapshots of a document; returns how many were removed.
+#[tauri::command]
+pub fn prune_snapshots(state: State<'_, AppState>, project_path: String, doc_id: String, keep: u32) -> Result<usize, AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+
+    let tx = conn.transaction()?;
+    let deleted = tx.execute(
+        "DELETE FROM Snapshot
+         WHERE document_id=?1 AND id NOT IN (
+           SELECT id FROM Snapshot WHERE document_id=?1
+           ORDER BY created_at DESC, rowid DESC
+           LIMIT ?2
+         )",
+        params![doc_id, keep],
+    )?;
+    tx.commit()?;
+    Ok(deleted)
+}
+
+// Every file a backup covers, as (name inside the zip, path on disk).
+fn backup_files(project_path: &str) -> Vec<(String, PathBuf)> {
+    let root = Path::new(project_path);
+    let mut files = vec![("project.db".to_string(), root.join("project.db"))];
+
+    // md/ mirrors and assets/ (character images), stored relative to the project root.
+    for sub in ["md", "assets"] {
+        let dir = root.join(sub);
+        if !dir.exists() {
+            continue;
+        }
+        for entry in walkdir::WalkDir::new(&dir).into_iter().flatten().filter(|e| e.file_type().is_file()) {
+            let rel = entry.path().strip_prefix(root).unwrap();
+            // Zip entries always use forward slashes, even on Windows.
+            let name = rel.to_string_lossy().replace('\\', "/");
+            files.push((name, entry.path().to_path_buf()));
+        }
+    }
+    files
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(bytes).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+const BACKUP_MANIFEST: &str = "manifest.json";
+
+// Every backup carries `manifest.json`: `files` maps each project file to its
+// sha256 and `base` names the backup an incremental one builds on (null for a
+// full backup).
+#[derive(Serialize, Deserialize)]
+struct BackupManifest {
+    base: Option<String>,
+    files: std::collections::BTreeMap<String, String>,
+}
+
+// Backups made before manifests existed are full backups; hash their entries.
+fn read_manifest(archive: &mut zip::ZipArchive<std::fs::File>) -> Result<BackupManifest, AppError> {
+    if let Ok(entry) = archive.by_name(BACKUP_MANIFEST) {
+        return serde_json::from_reader(entry)
+            .map_err(|e| AppError::Validation(format!("backup manifest is unreadable: {e}")));
+    }
+    let mut files = std::collections::BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        std::io::copy(&mut entry, &mut bytes)?;
+        files.insert(entry.name().to_string(), sha256_hex(&bytes));
+    }
+    Ok(BackupManifest { base: None, files })
+}
+
+fn open_backup(path: &Path) -> Result<zip::ZipArchive<std::fs::File>, AppError> {
+    Ok(zip::ZipArchive::new(std::fs::File::open(path)?)?)
+}
+
+// The backups an incremental at `backup_path` builds on, nearest first; empty
+// for a full backup. Bases are looked up next to `backup_path`.
+fn backup_bases(backup_path: &Path, manifest: &BackupManifest) -> Result<Vec<zip::ZipArchive<std::fs::File>>, AppError> {
+    let backups_dir = backup_path.parent().unwrap_or(Path::new("."));
+    let mut chain = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    let mut base = manifest.base.clone();
+    while let Some(name) = base {
+        if !seen.insert(name.clone()) {
+            return Err(AppError::Validation(format!("backup chain loops at {name}")));
+        }
+        let path = backups_dir.join(&name);
+        if !path.is_file() {
+            return Err(AppError::Validation(format!("backup chain is broken: {name} is missing")));
+        }
+        let mut archive = open_backup(&path)?;
+        base = read_manifest(&mut archive)?.base;
+        chain.push(archive);
+    }
+    Ok(chain)
+}
+
+// The body of write_backup: everything up to the final rename, so a failure
+// or cancel anywhere in here leaves only `tmp_path` to clean up.
+fn write_backup_zip(
+    state: &AppState,
+    project_path: &str,
+    tmp_path: &Path,
+    sources: &[(String, PathBuf)],
+    base: Option<(String, std::collections::BTreeMap<String, String>)>,
+    opts: zip::write::FileOptions,
+    progress: &dyn Fn(usize, usize),
+) -> Result<(), AppError> {
+    use std::io::Write;
+
+    let mut zipw = zip::ZipWriter::new(std::fs::File::create(tmp_path)?);
+
+    // Fold the WAL into project.db so the copied file has every committed write.
+    // A read-only project can't checkpoint, and has nothing of ours to fold in.
+    if !state.is_readonly(project_path) {
+        state.conn(project_path)?.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+    }
+
+    let mut files = std::collections::BTreeMap::new();
+    for (i, (entry_name, path)) in sources.iter().enumerate() {
+        state.check_cancelled()?;
+        progress(i, sources.len());
+        let entry_name = entry_name.clone();
+        let bytes = std::fs::read(path)?;
+        let hash = sha256_hex(&bytes);
+        let unchanged = base.as_ref().is_some_and(|(_, prev)| prev.get(&entry_name) == Some(&hash));
+        if !unchanged {
+            zipw.start_file(entry_name.as_str(), opts)?;
+            zipw.write_all(&bytes)?;
+        }
+        files.insert(entry_name, hash);
+    }
+
+    let manifest = BackupManifest { base: base.map(|(n, _)| n), files };
+    zipw.start_file(BACKUP_MANIFEST, opts)?;
+    zipw.write_all(&serde_json::to_vec_pretty(&manifest).expect("manifest serializes"))?;
+
+    let file = zipw.finish()?;
+    file.sync_all()?;
+    Ok(())
+}
+
+// Shared by backup_project and backup_project_incremental. An incremental
+// backup stores only files whose hash differs from the newest existing
+// backup's manifest; with no earlier backup it stores everything.
+fn write_backup(
+    state: &AppState,
+    project_path: &str,
+    incremental: bool,
+    compression: Option<&str>,
+    progress: &dyn Fn(usize, usize),
+) -> Result<(), AppError> {
+    let opts = backup_file_options(compression)?;
+    state.begin_operation();
+
+    let ts = Utc::now().format("%Y%m%d_%H%M%S");
+    // Only backups/ gets written, so a read-only project can still be backed up.
+    let backups = Path::new(project_path).join("backups");
+    fs::create_dir_all(&backups)?;
+    let name = if incremental { format!("backup_{ts}_incr.zip") } else { format!("backup_{ts}.zip") };
+    let backup_path = backups.join(&name);
+    if backup_path.exists() {
+        // An incremental would otherwise end up as its own base.
+        return Err(AppError::Conflict(format!("{name} already exists")));
+    }
+
+    let base = match backup_entries(project_path)?.into_iter().next() {
+        Some((_, base_name, _)) if incremental => {
+            let manifest = read_manifest(&mut open_backup(&backups.join(&base_name))?)?;
+            Some((base_name, manifest.files))
+        }
+        _ => None,
+    };
+
+    // Write under a .tmp name and rename once complete, like atomic_write, so a
+    // crash mid-backup never leaves a truncated zip that looks real.
+    let tmp_path = backups.join(format!("{name}.tmp"));
+    let sources = backup_files(project_path);
+    if let Err(e) = write_backup_zip(state, project_path, &tmp_path, &sources, base, opts, progress) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    std::fs::rename(&tmp_path, &backup_path)?;
+    progress(sources.len(), sources.len());
+    Ok(())
+}
+
+// `compression` for the backup commands: "store" (no compression, fastest to
+// write and restore), "fast" or "best"; unset is Deflate at its default level.
+fn backup_file_options(compression: Option<&str>) -> Result<zip::write::FileOptions, AppError> {
+    let deflated = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    Ok(match compression {
+        None => deflated,
+        Some("store") => zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored),
+        Some("fast") => deflated.compression_level(Some(1)),
+        Some("best") => deflated.compression_level(Some(9)),
+        Some(other) => {
+            return Err(AppError::Validation(format!("unknown compression {other:?}; expected store, fast or best")))
+        }
+    })
+}
+
+/// Stop the running backup or export at its next file; it fails with
+/// `cancelled` and removes whatever it had written.
+#[tauri::command]
+pub fn cancel_operation(state: State<'_, AppState>) {
+    state.request_cancel();
+}
+
+/// Zip up the project into `backups/`, reporting `backup-progress` per file.
+#[tauri::command]
+pub fn backup_project(state: State<'_, AppState>, window: tauri::Window, project_path: String, compression: Option<String>) -> Result<(), AppError> {
+    let progress = |done, total| emit_progress(&window, "backup-progress", done, total);
+    write_backup(&state, &project_path, false, compression.as_deref(), &progress)
+}
+
+/// Back up only what changed since the newest backup in `backups/`. Restoring
+/// it with `restore_project` pulls unchanged files from the earlier backups
+/// in its chain, so those must be kept alongside it.
+#[tauri::command]
+pub fn backup_project_incremental(
+    state: State<'_, AppState>,
+    window: tauri::Window,
+    project_path: String,
+    compression: Option<String>,
+) -> Result<(), AppError> {
+    let progress = |done, total| emit_progress(&window, "backup-progress", done, total);
+    write_backup(&state, &project_path, true, compression.as_deref(), &progress)
+}
+
+// (created, file name, size) of every `backups/*.zip`, newest first. The time
+// comes from the `backup_<ts>[_incr].zip` name, or the file mtime for zips named some
+// other way.
+fn backup_entries(project_path: &str) -> Result<Vec<(chrono::DateTime<Utc>, String, u64)>, AppError> {
+    let dir = Path::new(project_path).join("backups");
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut out = Vec::new();
+    for entry in fs::read_dir(&dir)?.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let meta = entry.metadata()?;
+        // Skips `.zip.tmp` partials from an interrupted backup too.
+        if !meta.is_file() || !name.ends_with(".zip") {
+            continue;
+        }
+
+        let from_name = name
+            .strip_prefix("backup_")
+            .and_then(|n| n.strip_suffix(".zip"))
+            .map(|n| n.strip_suffix("_incr").unwrap_or(n))
+            .and_then(|ts| chrono::NaiveDateTime::parse_from_str(ts, "%Y%m%d_%H%M%S").ok())
+            .map(|t| t.and_utc());
+        let created = match from_name {
+            Some(t) => t,
+            None => meta.modified()?.into(),
+        };
+        out.push((created, name, meta.len()));
+    }
+
+    out.sort_by_key(|(created, _, _)| std::cmp::Reverse(*created));
+    Ok(out)
+}
+
+/// Every `backups/*.zip` with its size and creation time, newest first.
+#[tauri::command]
+pub fn list_backups(project_path: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let entries = backup_entries(&project_path)?;
+    Ok(entries
+        .into_iter()
+        .map(|(created, name, size)| {
+            serde_json::json!({
+                "name": name,
+                "sizeBytes": size,
+                "createdAt": created.to_rfc3339(),
+            })
+        })
+        .collect())
+}
+
+/// Keep the `keep` newest backup zips, plus any older ones an incremental
+/// backup among them still builds on; returns how many were removed.
+#[tauri::command]
+pub fn prune_backups(state: State<'_, AppState>, project_path: String, keep: usize) -> Result<usize, AppError> {
+    state.ensure_writable(&project_path)?;
+    prune_backup_files(&project_path, keep)
+}
+
+fn prune_backup_files(project_path: &str, keep: usize) -> Result<usize, AppError> {
+    let dir = Path::new(project_path).join("backups");
+    let names: Vec<String> = backup_entries(project_path)?.into_iter().map(|(_, name, _)| name).collect();
+
+    let mut needed: std::collections::HashSet<String> = names.iter().take(keep).cloned().collect();
+    let mut pending: Vec<String> = needed.iter().cloned().collect();
+    while let Some(name) = pending.pop() {
+        let path = dir.join(&name);
+        if !path.is_file() {
+            // A base that's already gone; there's nothing left to protect.
+            continue;
+        }
+        // Any older zip might be this one's base, so don't guess past a bad manifest.
+        let manifest = open_backup(&path)
+            .and_then(|mut archive| read_manifest(&mut archive))
+            .map_err(|e| AppError::Validation(format!("cannot read {name}, so nothing was pruned: {e}")))?;
+        if let Some(base) = manifest.base {
+            if needed.insert(base.clone()) {
+                pending.push(base);
+            }
+        }
+    }
+
+    let stale: Vec<&String> = names.iter().filter(|n| !needed.contains(*n)).collect();
+    for name in &stale {
+        fs::remove_file(dir.join(name))?;
+    }
+    Ok(stale.len())
+}
+
+/// Check a backup without restoring it: every entry must decompress cleanly and
+/// `project.db` must pass `PRAGMA integrity_check`. Files an incremental backup
+/// left out are checked in the earlier backups it builds on. Problems are reported in
+/// `issues` rather than as an error, so the UI can show all of them at once.
+#[tauri::command]
+pub fn verify_backup(backup_path: String) -> Result<serde_json::Value, AppError> {
+    use std::io::Write;
+
+    let file = std::fs::File::open(&backup_path)?;
+    let mut issues: Vec<String> = Vec::new();
+    let mut archive = match zip::ZipArchive::new(file) {
+        Ok(a) => a,
+        Err(e) => {
+            issues.push(format!("not a readable zip archive: {e}"));
+            return Ok(serde_json::json!({ "valid": false, "dbOk": false, "mdFiles": 0, "issues": issues }));
+        }
+    };
+
+    // Reading an entry to the end verifies its CRC.
+    let mut md_files = 0;
+    let mut db_bytes: Option<Vec<u8>> = None;
+    let mut tally = |name: &str, bytes: Vec<u8>| {
+        if name == "project.db" {
+            db_bytes = Some(bytes);
+        } else if name.starts_with("md/") && name.ends_with(".md") {
+            md_files += 1;
+        }
+    };
+    let mut present = std::collections::HashSet::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = entry.name().to_string();
+        let mut bytes = Vec::new();
+        if let Err(e) = std::io::copy(&mut entry, &mut bytes) {
+            issues.push(format!("{name}: {e}"));
+            continue;
+        }
+        tally(&name, bytes);
+        present.insert(name);
+    }
+
+    // An incremental backup leaves unchanged files to the backups it builds
+    // on; check those copies the way restore_project would find them.
+    if archive.by_name(BACKUP_MANIFEST).is_ok() {
+        let chain = read_manifest(&mut archive)
+            .and_then(|manifest| Ok((backup_bases(Path::new(&backup_path), &manifest)?, manifest)));
+        match chain {
+            Err(e) => issues.push(e.to_string()),
+            Ok((mut bases, manifest)) => {
+                for (name, hash) in manifest.files.iter().filter(|(name, _)| !present.contains(*name)) {
+                    let Some(mut entry) = bases.iter_mut().find_map(|a| a.by_name(name).ok()) else {
+                        issues.push(format!("backup chain has no copy of {name}"));
+                        continue;
+                    };
+                    let mut bytes = Vec::new();
+                    if let Err(e) = std::io::copy(&mut entry, &mut bytes) {
+                        issues.push(format!("{name}: {e}"));
+                        continue;
+                    }
+                    if sha256_hex(&bytes) != *hash {
+                        issues.push(format!("{name} does not match the backup manifest"));
+                        continue;
+                    }
+                    tally(name, bytes);
+                }
+            }
+        }
+    }
+
+    let db_ok = match db_bytes {
+        None => {
+            issues.push("backup does not contain project.db".into());
+            false
+        }
+        Some(bytes) => {
+            let mut tmp = tempfile::NamedTempFile::new()?;
+            tmp.write_all(&bytes)?;
+            tmp.flush()?;
+            let check = Connection::open(tmp.path())
+                .and_then(|c| c.query_row("PRAGMA integrity_check", [], |r| r.get::<_, String>(0)));
+            match check {
+                Ok(res) if res == "ok" => true,
+                Ok(res) => {
+                    issues.push(format!("project.db integrity check: {res}"));
+                    false
+                }
+                Err(e) => {
+                    issues.push(format!("project.db is not a valid database: {e}"));
+                    false
+                }
+            }
+        }
+    };
+
+    Ok(serde_json::json!({
+        "valid": issues.is_empty(),
+        "dbOk": db_ok,
+        "mdFiles": md_files,
+        "issues": issues,
+    }))
+}
+
+// A WAL this big means checkpoints aren't keeping up (SQLite's own
+// autocheckpoint holds it near 4 MiB), usually because a reader never lets go.
+const WAL_WARN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Check the open project's own database, the live counterpart of
+/// `verify_backup`: `PRAGMA integrity_check`, `PRAGMA foreign_key_check` and
+/// the size of the WAL. Problems land in `issues`; `healthy` is true when
+/// there are none.
+#[tauri::command]
+pub fn health_check(state: State<'_, AppState>, project_path: String) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+    let mut issues: Vec<String> = Vec::new();
+
+    // One "ok" row when healthy, otherwise one row per problem.
+    let integrity: Vec<String> = match conn.prepare("PRAGMA integrity_check") {
+        Ok(mut st) => st.query_map([], |r| r.get(0))?.filter_map(|r| r.ok()).collect(),
+        Err(e) => vec![e.to_string()],
+    };
+    let integrity_ok = integrity.len() == 1 && integrity[0] == "ok";
+    if !integrity_ok {
+        issues.extend(integrity.iter().map(|m| format!("integrity check: {m}")));
+    }
+
+    let mut st = conn.prepare("PRAGMA foreign_key_check")?;
+    let violations: Vec<(String, Option<i64>, String)> = st
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+        .filter_map(|r| r.ok())
+        .collect();
+    for (table, rowid, parent) in &violations {
+        let row = rowid.map(|id| format!("row {id}")).unwrap_or_else(|| "a row".into());
+        issues.push(format!("{table} {row} points at a missing {parent}"));
+    }
+
+    let root = Path::new(&project_path);
+    let wal_bytes = fs::metadata(root.join("project.db-wal")).map(|m| m.len()).unwrap_or(0);
+    if wal_bytes > WAL_WARN_BYTES {
+        issues.push(format!("write-ahead log is {} MiB; checkpoints are not keeping up", wal_bytes / (1024 * 1024)));
+    }
+    let db_bytes = fs::metadata(root.join("project.db"))?.len();
+
+    Ok(serde_json::json!({
+        "healthy": issues.is_empty(),
+        "integrityOk": integrity_ok,
+        "foreignKeyViolations": violations.len(),
+        "dbBytes": db_bytes,
+        "walBytes": wal_bytes,
+        "issues": issues,
+    }))
+}
+
+/// Restore a backup into `dest_dir`. For an incremental backup the earlier
+/// backups it builds on are read from the same directory and each file is
+/// taken from the newest backup in the chain that holds it. A non-empty
+/// `dest_dir` is refused unless `force` is set, which closes the project
+/// there and discards its WAL before writing over it.
+#[tauri::command]
+pub fn restore_project(state: State<'_, AppState>, backup_path: String, dest_dir: String, force: bool) -> Result<String, AppError> {
+    restore_backup(&state, Path::new(&backup_path), &dest_dir, force)
+}
+
+fn restore_backup(state: &AppState, backup_path: &Path, dest_dir: &str, force: bool) -> Result<String, AppError> {
+    let mut target = open_backup(backup_path)?;
+    let manifest = read_manifest(&mut target)?;
+    if !manifest.files.contains_key("project.db") {
+        return Err(AppError::Validation("backup does not contain project.db".into()));
+    }
+
+    // Newest first: the target, then each base in turn.
+    let mut chain = vec![target];
+    chain.extend(backup_bases(backup_path, &manifest)?);
+
+    let dest = Path::new(dest_dir);
+    if dest.exists() {
+        let non_empty = std::fs::read_dir(dest)?.next().is_some();
+        if non_empty && !force {
+            return Err(AppError::Validation("destination is not empty".into()));
+        }
+        // Overwriting what may be an open project: let go of our connections,
+        // and drop its WAL so it can't be replayed onto the restored database.
+        state.close(dest_dir);
+        for side in ["project.db-wal", "project.db-shm"] {
+            match fs::remove_file(dest.join(side)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+    }
+    std::fs::create_dir_all(dest)?;
+
+    for (name, hash) in &manifest.files {
+        let Some(mut entry) = chain.iter_mut().find_map(|a| a.by_name(name).ok()) else {
+            return Err(AppError::Validation(format!("backup chain has no copy of {name}")));
+        };
+        // enclosed_name() rejects absolute paths and `..` components (zip-slip).
+        let rel = entry
+            .enclosed_name()
+            .map(|p| p.to_path_buf())
+            .ok_or_else(|| AppError::Validation(format!("unsafe path in backup: {name}")))?;
+        let mut bytes = Vec::new();
+        std::io::copy(&mut entry, &mut bytes)?;
+        if sha256_hex(&bytes) != *hash {
+            return Err(AppError::Validation(format!("{name} does not match the backup manifest")));
+        }
+
+        let out_path = dest.join(rel);
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        atomic_write(&out_path, &bytes)?;
+    }
+
+    std::fs::create_dir_all(dest.join("backups")).ok();
+    Ok(dest.to_string_lossy().to_string())
+}
+
+// ----------------- Characters
+
+/// Returns the new character as `load_character` would, seeded defaults included.
+#[tauri::command]
+pub fn create_character(state: State<'_, AppState>, window: tauri::Window, project_path: String, name: String, folder_id: Option<String>) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    let id = new_id();
+    conn.execute(
+        "INSERT INTO Character(id, project_id, folder_id, name, age, nationality, sexuality, height, attributes, image_path)
+         VALUES(?, 'p1', ?, ?, '', '', '', '', '[]', '')",
+        params![id, folder_id, name],
+    )?;
+    let character = select_character(&conn, &id)?;
+    emit_tree_changed(&window, TreeChange::characters("created", vec![id]));
+    Ok(character)
+}
+
+#[tauri::command]
+pub fn load_character(state: State<'_, AppState>, project_path: String, char_id: String) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+    select_character(&conn, &char_id)
+}
+
+// Column list and row shape shared by select_character and list_characters.
+const CHARACTER_COLUMNS: &str =
+    "id, name, folder_id, age, nationality, sexuality, height, attributes, image_path, thumbnail_path, age_years";
+
+fn character_json(r: &rusqlite::Row) -> rusqlite::Result<serde_json::Value> {
+    Ok(serde_json::json!({
+        "id": r.get::<_, String>(0)?,
+        "name": r.get::<_, String>(1)?,
+        "folderId": r.get::<_, Option<String>>(2)?,
+        "age": r.get::<_, Option<String>>(3)?,
+        "nationality": r.get::<_, Option<String>>(4)?,
+        "sexuality": r.get::<_, Option<String>>(5)?,
+        "height": r.get::<_, Option<String>>(6)?,
+        "attributes": parse_attributes(r.get(7)?),
+        "image": r.get::<_, Option<String>>(8)?,
+        "thumbnail": r.get::<_, Option<String>>(9)?,
+        "ageYears": r.get::<_, Option<i64>>(10)?,
+    }))
+}
+
+// Shared by load_character and export_character.
+fn select_character(conn: &Connection, char_id: &str) -> Result<serde_json::Value, AppError> {
+    let mut st = conn.prepare(&format!("SELECT {CHARACTER_COLUMNS} FROM Character WHERE id=?"))?;
+    Ok(st.query_row([char_id], character_json)?)
+}
+
+/// Every live character with the full `load_character` shape, by name, for
+/// views that would otherwise load them one at a time.
+#[tauri::command]
+pub fn list_characters(state: State<'_, AppState>, project_path: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let conn = state.conn(&project_path)?;
+    let mut st = conn.prepare(&format!(
+        "SELECT {CHARACTER_COLUMNS} FROM Character WHERE deleted_at IS NULL ORDER BY name COLLATE NOCASE, id"
+    ))?;
+    let rows = st.query_map([], character_json)?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+// `age_years` for a free-text age: set only when it's a plain whole number,
+// matching the backfill in migration 0017.
+fn parse_age_years(age: &str) -> Option<i64> {
+    let age = age.trim();
+    if age.is_empty() || age.len() > 9 || !age.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    age.parse().ok()
+}
+
+// Accept attributes as a JSON array or as its string encoding; store them as
+// canonical `[{"key":..,"value":..}]` text.
+fn normalize_attributes(v: Option<&serde_json::Value>) -> Result<String, AppError> {
+    let parsed: Option<Vec<Attribute>> = match v {
+        None | Some(serde_json::Value::Null) => Some(Vec::new()),
+        Some(serde_json::Value::String(s)) if s.trim().is_empty() => Some(Vec::new()),
+        Some(serde_json::Value::String(s)) => serde_json::from_str(s).ok(),
+        Some(v) => serde_json::from_value(v.clone()).ok(),
+    };
+    let attrs = parsed.ok_or_else(|| AppError::Validation("attributes must be an array of key/value objects".into()))?;
+    Ok(serde_json::to_string(&attrs).unwrap_or_else(|_| "[]".into()))
+}
+
+// Stored attributes text as a JSON array; rows saved before validation existed
+// may hold anything, which reads back as empty.
+fn parse_attributes(raw: Option<String>) -> serde_json::Value {
+    raw.and_then(|a| serde_json::from_str::<Vec<serde_json::Value>>(&a).ok())
+        .map(serde_json::Value::Array)
+        .unwrap_or_else(|| serde_json::json!([]))
+}
+
+#[tauri::command]
+pub fn save_character(state: State<'_, AppState>, project_path: String, char_id: String, data: serde_json::Value) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+
+    let age = data.get("age").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let nationality = data.get("nationality").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let sexuality = data.get("sexuality").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let height = data.get("height").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let attributes_value = normalize_attributes(data.get("attributes"))?;
+    let image = data.get("image").and_then(|v| v.as_str()).unwrap_or("").to_string();
+
+    // A different image makes the thumbnail stale, so drop it.
+    conn.execute(
+        "UPDATE Character
+         SET age=?1, nationality=?2, sexuality=?3, height=?4, attributes=?5, image_path=?6,
+             thumbnail_path=CASE WHEN image_path IS ?6 THEN thumbnail_path ELSE NULL END,
+             age_years=?8, updated_at=CURRENT_TIMESTAMP
+         WHERE id=?7",
+        params![age, nationality, sexuality, height, attributes_value, image, char_id, parse_age_years(&age)],
+    )?;
+    Ok(())
+}
+
+/// Live characters as `{ id, name, folderId, age, ageYears }`, ordered by
+/// `by`: "name", or "age" (youngest first; those without a numeric age last,
+/// by name).
+#[tauri::command]
+pub fn list_characters_sorted(state: State<'_, AppState>, project_path: String, by: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let order = match by.as_str() {
+        "name" => "name COLLATE NOCASE, id",
+        "age" => "age_years IS NULL, age_years, name COLLATE NOCASE, id",
+        other => return Err(AppError::Validation(format!("cannot sort characters by {other:?}; expected name or age"))),
+    };
+    let conn = state.conn(&project_path)?;
+    let mut st = conn.prepare(&format!(
+        "SELECT id, name, folder_id, age, age_years FROM Character WHERE deleted_at IS NULL ORDER BY {order}"
+    ))?;
+    let rows = st.query_map([], |r| {
+        Ok(serde_json::json!({
+            "id": r.get::<_, String>(0)?,
+            "name": r.get::<_, String>(1)?,
+            "folderId": r.get::<_, Option<String>>(2)?,
+            "age": r.get::<_, Option<String>>(3)?,
+            "ageYears": r.get::<_, Option<i64>>(4)?,
+        }))
+    })?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+#[tauri::command]
+pub fn rename_character(state: State<'_, AppState>, window: tauri::Window, project_path: String, char_id: String, new_name: String) -> Result<(), AppError> {
+    let name = new_name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation("character name cannot be empty".into()));
+    }
+
+    let conn = state.conn_mut(&project_path)?;
+    let changed = conn.execute(
+        "UPDATE Character SET name=?, updated_at=CURRENT_TIMESTAMP WHERE id=?",
+        params![name, char_id],
+    )?;
+    if changed == 0 {
+        return Err(AppError::NotFound("character does not exist".into()));
+    }
+    emit_tree_changed(&window, TreeChange::characters("renamed", vec![char_id]));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn move_character(state: State<'_, AppState>, window: tauri::Window, project_path: String, char_id: String, new_folder_id: Option<String>) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+
+    ensure_folder_exists(&conn, new_folder_id.as_deref())?;
+
+    // Assets live under the char id, not the folder, so only the row changes.
+    let changed = conn.execute(
+        "UPDATE Character SET folder_id=?, updated_at=CURRENT_TIMESTAMP WHERE id=?",
+        params![new_folder_id, char_id],
+    )?;
+    if changed == 0 {
+        return Err(AppError::NotFound("character does not exist".into()));
+    }
+    emit_tree_changed(&window, TreeChange::characters("moved", vec![char_id]));
+    Ok(())
+}
+
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "gif"];
+// Longest edge of the thumbnail import_character_image writes next to the image.
+const THUMBNAIL_MAX_PX: u32 = 256;
+
+#[tauri::command]
+pub fn import_character_image(
+    state: State<'_, AppState>,
+    project_path: String,
+    char_id: String,
+    source_path: String,
+) -> Result<String, AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    // Before copying anything, so an unknown id leaves no stray asset directory.
+    let n: i64 = conn.query_row("SELECT COUNT(*) FROM Character WHERE id=?", [&char_id], |r| r.get(0))?;
+    if n == 0 {
+        return Err(AppError::NotFound("character does not exist".into()));
+    }
+
+    let (dest_path, thumb_path) = import_image_file(&project_path, &char_id, &source_path)?;
+    conn.execute(
+        "UPDATE Character SET image_path=?, thumbnail_path=? WHERE id=?",
+        params![dest_path.to_string_lossy(), thumb_path.to_string_lossy(), char_id],
+    )?;
 
     Ok(dest_path.to_string_lossy().to_string())
 }
 
+/// One entry per `source_paths` item from import_character_images.
+#[derive(Serialize, TS)]
+#[ts(export, export_to = "bindings.ts")]
+pub struct ImageImportResult { pub source: String, pub ok: bool, pub error: Option<String> }
+
+/// Import several reference images (a drag-drop of a whole folder, say) into
+/// the character's CharacterImage list. A file that's missing, not an image
+/// or over the size cap is skipped with its error; the rest still go in.
+/// Re-importing a file name replaces the earlier copy.
+#[tauri::command]
+pub fn import_character_images(
+    state: State<'_, AppState>,
+    project_path: String,
+    char_id: String,
+    source_paths: Vec<String>,
+) -> Result<Vec<ImageImportResult>, AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    let n: i64 = conn.query_row("SELECT COUNT(*) FROM Character WHERE id=?", [&char_id], |r| r.get(0))?;
+    if n == 0 {
+        return Err(AppError::NotFound("character does not exist".into()));
+    }
+
+    let mut results = Vec::new();
+    for source in source_paths {
+        let imported = import_image_file(&project_path, &char_id, &source).and_then(|(image, thumb)| {
+            let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            conn.execute(
+                "INSERT INTO CharacterImage(id, character_id, file_name, thumbnail_name) VALUES(?,?,?,?)
+                 ON CONFLICT(character_id, file_name) DO UPDATE SET thumbnail_name=excluded.thumbnail_name",
+                params![new_id(), char_id, name(&image), name(&thumb)],
+            )?;
+            Ok(())
+        });
+        let error = imported.err().map(|e| e.to_string());
+        results.push(ImageImportResult { source, ok: error.is_none(), error });
+    }
+    Ok(results)
+}
+
+// Check `source_path` is an image we accept and copy it, plus a PNG thumbnail,
+// into PROJECT/assets/characters/<char_id>/. Returns (image, thumbnail) paths.
+fn import_image_file(project_path: &str, char_id: &str, source_path: &str) -> Result<(PathBuf, PathBuf), AppError> {
+    if source_path.trim().is_empty() {
+        return Err(AppError::Validation("source_path is empty".into()));
+    }
+
+    let src = Path::new(source_path);
+    if !src.exists() || !src.is_file() {
+        return Err(AppError::NotFound("source file does not exist".into()));
+    }
+
+    let ext = src
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    if !IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        return Err(AppError::Validation(format!("unsupported image type; expected one of: {}", IMAGE_EXTENSIONS.join(", "))));
+    }
+
+    let size = fs::metadata(src)?.len();
+    if size > MAX_IMAGE_BYTES {
+        return Err(AppError::Validation(format!("image is too large ({} MB max)", MAX_IMAGE_BYTES / (1024 * 1024))));
+    }
+
+    validate_char_id(char_id)?;
+
+    // destination: PROJECT/assets/characters/<char_id>/<filename>
+    let chars_root = Path::new(project_path).join("assets").join("characters");
+    let dest_dir = chars_root.join(char_id);
+    fs::create_dir_all(&dest_dir)?;
+
+    // Compare canonical paths so a symlinked directory can't escape.
+    let root_canon = chars_root.canonicalize()?;
+    let dest_dir = dest_dir.canonicalize()?;
+    if dest_dir.parent() != Some(root_canon.as_path()) {
+        return Err(AppError::Validation("destination escapes the character assets directory".into()));
+    }
+
+    let filename = src.file_name().ok_or_else(|| AppError::Validation("invalid filename".into()))?;
+    let dest_path: PathBuf = dest_dir.join(filename);
+
+    // Decode before copying anything so a corrupt file is refused outright.
+    let img = image::open(src).map_err(|e| AppError::Validation(format!("could not read image: {e}")))?;
+    let thumb = if img.width() > THUMBNAIL_MAX_PX || img.height() > THUMBNAIL_MAX_PX {
+        img.thumbnail(THUMBNAIL_MAX_PX, THUMBNAIL_MAX_PX)
+    } else {
+        img
+    };
+
+    // The full file name, extension included, so a.jpg and a.png don't share one.
+    let thumb_path = dest_dir.join(format!("{}.thumb.png", filename.to_string_lossy()));
+    if let Err(e) = thumb.save_with_format(&thumb_path, image::ImageFormat::Png) {
+        let _ = fs::remove_file(&thumb_path);
+        return Err(AppError::Validation(format!("could not write thumbnail: {e}")));
+    }
+
+    // copy (overwrite if same name already exists); the thumbnail goes with a failure
+    if let Err(e) = fs::copy(src, &dest_path) {
+        let _ = fs::remove_file(&thumb_path);
+        return Err(e.into());
+    }
+
+    Ok((dest_path, thumb_path))
+}
+
+// ------- Writing sessions
+
+const SESSION_HISTORY_DEFAULT_DAYS: u32 = 30;
+
+/// Start a writing session; until `end_session`, every `save_document` adds
+/// its change in word count to the session's `words_added`. Returns its id.
+#[tauri::command]
+pub fn start_session(state: State<'_, AppState>, project_path: String) -> Result<String, AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    let mut st = conn.prepare(
+        "SELECT Body.document_id, Body.markdown
+         FROM Body JOIN Document ON Body.document_id = Document.id
+         WHERE Document.deleted_at IS NULL",
+    )?;
+    let baseline = st
+        .query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?
+        .filter_map(|r| r.ok())
+        .map(|(id, md)| (id, text_stats(&md).0))
+        .collect();
+
+    let id = new_id();
+    state.begin_writing_session(&project_path, ActiveSession { id: id.clone(), baseline, latest: Default::default() })?;
+    // An open row left over from a quit without end_session keeps the words
+    // it had counted; it just needs an end.
+    let inserted = conn
+        .execute("UPDATE WritingSession SET ended_at=CURRENT_TIMESTAMP WHERE ended_at IS NULL", [])
+        .and_then(|_| conn.execute("INSERT INTO WritingSession(id) VALUES(?)", [&id]));
+    if let Err(e) = inserted {
+        state.finish_writing_session(&project_path);
+        return Err(e.into());
+    }
+    Ok(id)
+}
+
+/// End the running session and return it as `{ id, startedAt, endedAt, wordsAdded }`.
+#[tauri::command]
+pub fn end_session(state: State<'_, AppState>, project_path: String) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    let session = state
+        .finish_writing_session(&project_path)
+        .ok_or_else(|| AppError::NotFound("no writing session is running".into()))?;
+    let words_added = session.words_added();
+    let (started_at, ended_at): (String, String) = conn.query_row(
+        "UPDATE WritingSession SET ended_at=CURRENT_TIMESTAMP, words_added=? WHERE id=?
+         RETURNING started_at, ended_at",
+        params![words_added, session.id],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+    Ok(serde_json::json!({
+        "id": session.id,
+        "startedAt": started_at,
+        "endedAt": ended_at,
+        "wordsAdded": words_added,
+    }))
+}
+
+/// Sessions started in the last `days` local days (today counts as one;
+/// default 30), newest first, plus `days`: net words per local date for a
+/// streak view. A running session is included with a null `endedAt`.
+#[tauri::command]
+pub fn session_history(state: State<'_, AppState>, project_path: String, days: Option<u32>) -> Result<serde_json::Value, AppError> {
+    let conn = state.conn(&project_path)?;
+    let since = format!("-{} days", days.unwrap_or(SESSION_HISTORY_DEFAULT_DAYS).max(1));
+
+    let mut st = conn.prepare(
+        "SELECT id, started_at, ended_at, words_added FROM WritingSession
+         WHERE date(started_at, 'localtime') > date('now', 'localtime', ?)
+         ORDER BY started_at DESC, rowid DESC",
+    )?;
+    let sessions: Vec<serde_json::Value> = st
+        .query_map([&since], |r| {
+            Ok(serde_json::json!({
+                "id": r.get::<_, String>(0)?,
+                "startedAt": r.get::<_, String>(1)?,
+                "endedAt": r.get::<_, Option<String>>(2)?,
+                "wordsAdded": r.get::<_, i64>(3)?,
+            }))
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    let mut st = conn.prepare(
+        "SELECT date(started_at, 'localtime') AS day, SUM(words_added) FROM WritingSession
+         WHERE date(started_at, 'localtime') > date('now', 'localtime', ?)
+         GROUP BY day ORDER BY day DESC",
+    )?;
+    let per_day: Vec<serde_json::Value> = st
+        .query_map([&since], |r| {
+            Ok(serde_json::json!({ "date": r.get::<_, String>(0)?, "words": r.get::<_, i64>(1)? }))
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(serde_json::json!({ "sessions": sessions, "days": per_day }))
+}
+
+// ------- Templates
+
+/// Store the document's current body as template `template_name`, replacing
+/// any template already saved under that name.
+#[tauri::command]
+pub fn save_document_as_template(state: State<'_, AppState>, project_path: String, doc_id: String, template_name: String) -> Result<(), AppError> {
+    let name = template_name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation("template name cannot be empty".into()));
+    }
+
+    let conn = state.conn_mut(&project_path)?;
+    let md: String = conn
+        .query_row("SELECT markdown FROM Body WHERE document_id=?", [&doc_id], |r| r.get(0))
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("document does not exist".into()))?;
+    conn.execute(
+        "INSERT INTO Template(name, markdown) VALUES(?, ?)
+         ON CONFLICT(name) DO UPDATE SET markdown=excluded.markdown",
+        params![name, md],
+    )?;
+    Ok(())
+}
+
+/// Like `create_document`, seeded with the template's body.
+#[tauri::command]
+pub fn create_document_from_template(
+    state: State<'_, AppState>,
+    window: tauri::Window,
+    project_path: String,
+    title: String,
+    folder_id: Option<String>,
+    template_name: String,
+) -> Result<String, AppError> {
+    let mut conn = state.conn_mut(&project_path)?;
+    let md: String = conn
+        .query_row("SELECT markdown FROM Template WHERE name=?", [template_name.trim()], |r| r.get(0))
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("template {template_name} does not exist")))?;
+    let id = insert_document(&mut conn, &project_path, &title, folder_id.as_deref(), &md)?;
+    emit_tree_changed(&window, TreeChange::docs("created", vec![id.clone()]));
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn list_templates(state: State<'_, AppState>, project_path: String) -> Result<Vec<String>, AppError> {
+    let conn = state.conn(&project_path)?;
+    let mut st = conn.prepare("SELECT name FROM Template ORDER BY name COLLATE NOCASE")?;
+    let rows = st.query_map([], |r| r.get::<_, String>(0))?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+// ------- Tags
+
+/// Tag a document, creating the tag on first use. Returns the tag id.
+#[tauri::command]
+pub fn add_tag(state: State<'_, AppState>, project_path: String, doc_id: String, name: String) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation("tag name cannot be empty".into()));
+    }
+
+    let mut conn = state.conn_mut(&project_path)?;
+    let tx = conn.transaction()?;
+    tx.execute("INSERT OR IGNORE INTO Tag(id, name) VALUES(?, ?)", params![new_id(), name])?;
+    let tag_id: String = tx.query_row("SELECT id FROM Tag WHERE name=?", [name], |r| r.get(0))?;
+    tx.execute(
+        "INSERT OR IGNORE INTO DocumentTag(document_id, tag_id) VALUES(?, ?)",
+        params![doc_id, tag_id],
+    )?;
+    tx.commit()?;
+    Ok(tag_id)
+}
+
+#[tauri::command]
+pub fn remove_tag(state: State<'_, AppState>, project_path: String, doc_id: String, tag_id: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    conn.execute(
+        "DELETE FROM DocumentTag WHERE document_id=? AND tag_id=?",
+        params![doc_id, tag_id],
+    )?;
+    Ok(())
+}
+
+/// All tags with how many live documents carry each.
+#[tauri::command]
+pub fn list_tags(state: State<'_, AppState>, project_path: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let mut st = conn.prepare(
+        "SELECT Tag.id, Tag.name, COUNT(Document.id)
+         FROM Tag
+         LEFT JOIN DocumentTag ON DocumentTag.tag_id = Tag.id
+         LEFT JOIN Document ON Document.id = DocumentTag.document_id AND Document.deleted_at IS NULL
+         GROUP BY Tag.id
+         ORDER BY Tag.name ASC",
+    )?;
+    let rows = st.query_map([], |r| {
+        Ok(serde_json::json!({
+            "id": r.get::<_, String>(0)?,
+            "name": r.get::<_, String>(1)?,
+            "count": r.get::<_, i64>(2)?,
+        }))
+    })?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+/// Live documents carrying `tag_id`, in the same shape as `list_tree` docs.
+#[tauri::command]
+pub fn list_documents_by_tag(state: State<'_, AppState>, project_path: String, tag_id: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let mut st = conn.prepare(
+        "SELECT Document.id, Document.title, Document.folder_id
+         FROM Document JOIN DocumentTag ON DocumentTag.document_id = Document.id
+         WHERE DocumentTag.tag_id=? AND Document.deleted_at IS NULL
+         ORDER BY Document.title ASC",
+    )?;
+    let rows = st.query_map([tag_id], |r| {
+        Ok(serde_json::json!({
+            "id": r.get::<_, String>(0)?,
+            "title": r.get::<_, String>(1)?,
+            "folderId": r.get::<_, Option<String>>(2)?,
+        }))
+    })?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+// ------- Character relations
+
+/// Link `from_char` to `to_char` with a free-form `kind` ("ally", "parent", ...).
+#[tauri::command]
+pub fn add_relation(
+    state: State<'_, AppState>,
+    project_path: String,
+    from_char: String,
+    to_char: String,
+    kind: String,
+    note: Option<String>,
+) -> Result<String, AppError> {
+    let kind = kind.trim();
+    if kind.is_empty() {
+        return Err(AppError::Validation("relation kind cannot be empty".into()));
+    }
+    if from_char == to_char {
+        return Err(AppError::Validation("a character cannot be related to itself".into()));
+    }
+
+    let conn = state.conn_mut(&project_path)?;
+    for id in [&from_char, &to_char] {
+        let n: i64 = conn.query_row("SELECT COUNT(*) FROM Character WHERE id=?", [id], |r| r.get(0))?;
+        if n == 0 {
+            return Err(AppError::NotFound(format!("character {id} does not exist")));
+        }
+    }
+
+    let id = new_id();
+    conn.execute(
+        "INSERT INTO CharacterRelation(id, from_char, to_char, kind, note) VALUES(?,?,?,?,?)",
+        params![id, from_char, to_char, kind, note],
+    )?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn remove_relation(state: State<'_, AppState>, project_path: String, relation_id: String) -> Result<(), AppError> {
+    let conn = state.conn_mut(&project_path)?;
+    conn.execute("DELETE FROM CharacterRelation WHERE id=?", params![relation_id])?;
+    Ok(())
+}
+
+/// Both directions of `char_id`'s relations. `direction` is "outgoing" when
+/// `char_id` is the `from` side; `otherId`/`otherName` is the character at the other end.
+#[tauri::command]
+pub fn list_relations(state: State<'_, AppState>, project_path: String, char_id: String) -> Result<Vec<serde_json::Value>, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let mut st = conn.prepare(
+        "SELECT r.id, r.kind, r.note, 'outgoing', c.id, c.name
+         FROM CharacterRelation r JOIN Character c ON c.id = r.to_char
+         WHERE r.from_char = ?1
+         UNION ALL
+         SELECT r.id, r.kind, r.note, 'incoming', c.id, c.name
+         FROM CharacterRelation r JOIN Character c ON c.id = r.from_char
+         WHERE r.to_char = ?1
+         ORDER BY 6 ASC",
+    )?;
+    let rows = st.query_map([char_id], |r| {
+        Ok(serde_json::json!({
+            "id": r.get::<_, String>(0)?,
+            "kind": r.get::<_, String>(1)?,
+            "note": r.get::<_, Option<String>>(2)?,
+            "direction": r.get::<_, String>(3)?,
+            "otherId": r.get::<_, String>(4)?,
+            "otherName": r.get::<_, String>(5)?,
+        }))
+    })?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+// ------- Export
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn markdown_to_html(md: &str) -> String {
+    let mut out = String::new();
+    pulldown_cmark::html::push_html(&mut out, pulldown_cmark::Parser::new(md));
+    out
+}
+
+// Minimal standalone page; `body` is already-rendered HTML.
+fn html_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        html_escape(title),
+        body
+    )
+}
+
+/// Render a document to a standalone HTML file at `dest_path` (parent dirs are
+/// created). Returns the written path.
+#[tauri::command]
+pub fn export_document_html(state: State<'_, AppState>, project_path: String, doc_id: String, dest_path: String) -> Result<String, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let (title, md): (String, String) = conn.query_row(
+        "SELECT Document.title, Body.markdown
+         FROM Document JOIN Body ON Body.document_id = Document.id
+         WHERE Document.id=?",
+        [doc_id],
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+
+    let html = html_page(&title, &markdown_to_html(&md));
+    atomic_write(Path::new(&dest_path), html.as_bytes())?;
+    Ok(dest_path)
+}
+
+// (title, markdown) of every live document in tree order: a folder's own docs
+// by sort_order, then its subfolders by name, depth-first from the root.
+fn manuscript_docs(conn: &Connection, folder_id: Option<&str>, out: &mut Vec<(String, String)>) -> Result<(), AppError> {
+    let docs: Vec<(String, String)> = {
+        let mut st = conn.prepare(
+            "SELECT Document.title, Body.markdown
+             FROM Document JOIN Body ON Body.document_id = Document.id
+             WHERE Document.folder_id IS ? AND Document.deleted_at IS NULL
+             ORDER BY Document.sort_order ASC, Document.created_at ASC",
+        )?;
+        let rows = st.query_map([folder_id], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
+    };
+    out.extend(docs);
+
+    let child_ids: Vec<String> = {
+        let mut st = conn.prepare("SELECT id FROM Folder WHERE parent_id IS ? AND deleted_at IS NULL ORDER BY name ASC")?;
+        let rows = st.query_map([folder_id], |r| r.get::<_, String>(0))?;
+        rows.filter_map(Result::ok).collect()
+    };
+    for child in child_ids {
+        manuscript_docs(conn, Some(&child), out)?;
+    }
+    Ok(())
+}
+
+// Make a title usable as a file/dir name on every OS.
+fn sanitize_filename(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_control() || r#"/\:*?"<>|"#.contains(c) { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    if cleaned.is_empty() { "untitled".into() } else { cleaned.to_string() }
+}
+
+// `base` + ext, or `base (2)` + ext, ... whichever isn't in `taken` yet.
+fn unique_name(taken: &mut std::collections::HashSet<String>, base: &str, ext: &str) -> String {
+    let mut name = format!("{base}{ext}");
+    let mut n = 2;
+    while !taken.insert(name.to_lowercase()) {
+        name = format!("{base} ({n}){ext}");
+        n += 1;
+    }
+    name
+}
+
+// Walk the tree like manuscript_docs, collecting (zip path, markdown) for
+// every live doc plus an index line per folder and doc.
+fn bundle_entries(
+    conn: &Connection,
+    folder_id: Option<&str>,
+    dir: &str,
+    depth: usize,
+    files: &mut Vec<(String, String)>,
+    index: &mut Vec<String>,
+) -> Result<(), AppError> {
+    let mut taken = std::collections::HashSet::new();
+    taken.insert("index.md".to_string());
+
+    let docs: Vec<(String, String)> = {
+        let mut st = conn.prepare(
+            "SELECT Document.title, Body.markdown
+             FROM Document JOIN Body ON Body.document_id = Document.id
+             WHERE Document.folder_id IS ? AND Document.deleted_at IS NULL
+             ORDER BY Document.sort_order ASC, Document.created_at ASC",
+        )?;
+        let rows = st.query_map([folder_id], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
+    };
+    for (title, md) in docs {
+        let path = format!("{dir}{}", unique_name(&mut taken, &sanitize_filename(&title), ".md"));
+        // Spaces in link targets need escaping in markdown.
+        index.push(format!("{}- [{}]({})", "  ".repeat(depth), title, path.replace(' ', "%20")));
+        files.push((path, md));
+    }
+
+    let children: Vec<(String, String)> = {
+        let mut st = conn.prepare(
+            "SELECT id, name FROM Folder WHERE parent_id IS ? AND deleted_at IS NULL ORDER BY name ASC",
+        )?;
+        let rows = st.query_map([folder_id], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.filter_map(Result::ok).collect()
+    };
+    for (id, name) in children {
+        let sub = format!("{dir}{}/", unique_name(&mut taken, &sanitize_filename(&name), ""));
+        index.push(format!("{}- **{}**", "  ".repeat(depth), name));
+        bundle_entries(conn, Some(&id), &sub, depth + 1, files, index)?;
+    }
+    Ok(())
+}
+
+/// Zip of plain markdown for sharing: one `<title>.md` per document, in
+/// directories mirroring the folder tree, plus an `index.md` table of
+/// contents. Unlike `backup_project` this can't be restored. Returns the path.
+#[tauri::command]
+pub fn export_markdown_bundle(state: State<'_, AppState>, project_path: String, dest_path: String) -> Result<String, AppError> {
+    state.begin_operation();
+    let conn = state.conn(&project_path)?;
+    let mut files = Vec::new();
+    let mut index = Vec::new();
+    bundle_entries(&conn, None, "", 0, &mut files, &mut index)?;
+
+    let book = Path::new(&project_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let index_md = format!("# {book}\n\n{}\n", index.join("\n"));
+
+    let mut entries = vec![("index.md".to_string(), index_md)];
+    entries.extend(files);
+    write_zip_package(&state, &dest_path, &entries)?;
+    Ok(dest_path)
+}
+
+/// Concatenate the whole project into one file. `format` is "md" (raw
+/// markdown joined by `---` rules) or "html" (each doc rendered under an
+/// `<h1>` of its title). Returns the written path.
+#[tauri::command]
+pub fn export_manuscript(
+    state: State<'_, AppState>,
+    window: tauri::Window,
+    project_path: String,
+    dest_path: String,
+    format: String,
+) -> Result<String, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let mut docs = Vec::new();
+    manuscript_docs(&conn, None, &mut docs)?;
+    let total = docs.len();
+
+    let out = match format.as_str() {
+        "md" => docs
+            .iter()
+            .enumerate()
+            .map(|(i, (_, md))| {
+                emit_progress(&window, "export-progress", i + 1, total);
+                md.as_str()
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n---\n\n"),
+        "html" => {
+            let chapters: Vec<String> = docs
+                .iter()
+                .enumerate()
+                .map(|(i, (title, md))| {
+                    let chapter = format!("<h1>{}</h1>\n{}", html_escape(title), markdown_to_html(md));
+                    emit_progress(&window, "export-progress", i + 1, total);
+                    chapter
+                })
+                .collect();
+            let book = Path::new(&project_path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            html_page(&book, &chapters.join("<hr>\n"))
+        }
+        other => return Err(AppError::Validation(format!("unsupported export format: {other}"))),
+    };
+
+    atomic_write(Path::new(&dest_path), out.as_bytes())?;
+    emit_progress(&window, "export-progress", total, total);
+    Ok(dest_path)
+}
+
+// Like markdown_to_html, but raw HTML in the source is escaped as text: EPUB
+// chapters must be well-formed XHTML and arbitrary HTML rarely is.
+fn markdown_to_xhtml(md: &str) -> String {
+    use pulldown_cmark::Event;
+    let events = pulldown_cmark::Parser::new(md).map(|e| match e {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        e => e,
+    });
+    let mut out = String::new();
+    pulldown_cmark::html::push_html(&mut out, events);
+    out
+}
+
+fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+         <head>\n<meta charset=\"utf-8\"/>\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        html_escape(title),
+        body
+    )
+}
+
+// Write a zip package to `dest_path` via tmp-then-rename. An entry named
+// `mimetype` is stored uncompressed, as EPUB readers require.
+fn write_zip_package(state: &AppState, dest_path: &str, entries: &[(String, String)]) -> Result<(), AppError> {
+    use std::io::Write;
+
+    let dest = Path::new(dest_path);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = PathBuf::from(format!("{dest_path}.tmp"));
+    let write = || -> Result<(), AppError> {
+        let mut zipw = zip::ZipWriter::new(fs::File::create(&tmp)?);
+        let deflated = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let stored = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, contents) in entries {
+            state.check_cancelled()?;
+            zipw.start_file(name.as_str(), if name == "mimetype" { stored } else { deflated })?;
+            zipw.write_all(contents.as_bytes())?;
+        }
+        zipw.finish()?.sync_all()?;
+        Ok(())
+    };
+    // Never leave a half-written package behind, cancelled or not.
+    if let Err(e) = write() {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::rename(&tmp, dest)?;
+    Ok(())
+}
+
+/// Optional book metadata for `export_epub`; gaps are filled from the project
+/// folder name and the `author_name` setting.
+#[derive(Deserialize, Default)]
+pub struct EpubMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+}
+
+/// Export the manuscript as an EPUB3: one XHTML chapter per live document in
+/// tree order, a nav document and the OPF package. Returns the written path.
+#[tauri::command]
+pub fn export_epub(state: State<'_, AppState>, project_path: String, dest_path: String, metadata: Option<EpubMetadata>) -> Result<String, AppError> {
+    state.begin_operation();
+    let conn = state.conn(&project_path)?;
+    let mut docs = Vec::new();
+    manuscript_docs(&conn, None, &mut docs)?;
+    if docs.is_empty() {
+        return Err(AppError::Validation("the project has no documents to export".into()));
+    }
+
+    let meta = metadata.unwrap_or_default();
+    let non_empty = |v: Option<String>| v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    let title = non_empty(meta.title).unwrap_or_else(|| {
+        Path::new(&project_path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+    });
+    let setting_author: Option<String> = conn
+        .query_row("SELECT value FROM Settings WHERE key='author_name'", [], |r| r.get(0))
+        .optional()?
+        .flatten();
+    let author = non_empty(meta.author).or_else(|| non_empty(setting_author));
+    let language = non_empty(meta.language).unwrap_or_else(|| "en".into());
+    // Stable per project so readers treat a re-export as the same book.
+    let identifier = format!("urn:mingnote:{}", &sha256_hex(project_path.as_bytes())[..32]);
+    let modified = Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+
+    let mut entries = vec![
+        ("mimetype".to_string(), "application/epub+zip".to_string()),
+        (
+            "META-INF/container.xml".to_string(),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+             <rootfiles>\n<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n</rootfiles>\n\
+             </container>\n"
+                .to_string(),
+        ),
+    ];
+
+    let mut manifest = vec!["<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>".to_string()];
+    let mut spine = Vec::new();
+    let mut toc = Vec::new();
+    for (i, (doc_title, md)) in docs.iter().enumerate() {
+        state.check_cancelled()?;
+        let file = format!("chapter-{}.xhtml", i + 1);
+        let body = format!("<h1>{}</h1>\n{}", html_escape(doc_title), markdown_to_xhtml(md));
+        entries.push((format!("OEBPS/{file}"), xhtml_page(doc_title, &body)));
+        manifest.push(format!("<item id=\"c{}\" href=\"{file}\" media-type=\"application/xhtml+xml\"/>", i + 1));
+        spine.push(format!("<itemref idref=\"c{}\"/>", i + 1));
+        toc.push(format!("<li><a href=\"{file}\">{}</a></li>", html_escape(doc_title)));
+    }
+
+    let nav = format!(
+        "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n{}\n</ol>\n</nav>\n",
+        html_escape(&title),
+        toc.join("\n")
+    );
+    entries.push(("OEBPS/nav.xhtml".to_string(), xhtml_page(&title, &nav)));
+
+    let creator = author.map(|a| format!("<dc:creator>{}</dc:creator>\n", html_escape(&a))).unwrap_or_default();
+    let opf = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+         <dc:identifier id=\"book-id\">{identifier}</dc:identifier>\n\
+         <dc:title>{}</dc:title>\n\
+         {creator}\
+         <dc:language>{}</dc:language>\n\
+         <meta property=\"dcterms:modified\">{modified}</meta>\n\
+         </metadata>\n\
+         <manifest>\n{}\n</manifest>\n\
+         <spine>\n{}\n</spine>\n\
+         </package>\n",
+        html_escape(&title),
+        html_escape(&language),
+        manifest.join("\n"),
+        spine.join("\n"),
+    );
+    entries.push(("OEBPS/content.opf".to_string(), opf));
+
+    write_zip_package(&state, &dest_path, &entries)?;
+    Ok(dest_path)
+}
+
+const DOCX_CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+<Default Extension="xml" ContentType="application/xml"/>
+<Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/>
+<Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/>
+</Types>
+"#;
+
+const DOCX_PACKAGE_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/>
+</Relationships>
+"#;
+
+const DOCX_DOCUMENT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
+</Relationships>
+"#;
+
+// Standard manuscript format: 12pt Times New Roman, double-spaced, first-line
+// indents; each chapter heading starts a new page.
+const DOCX_STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:ascii="Times New Roman" w:hAnsi="Times New Roman" w:cs="Times New Roman"/><w:sz w:val="24"/></w:rPr></w:rPrDefault></w:docDefaults>
+<w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/><w:pPr><w:spacing w:after="0" w:line="480" w:lineRule="auto"/><w:ind w:firstLine="720"/></w:pPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:keepNext/><w:pageBreakBefore/><w:spacing w:after="480"/><w:ind w:firstLine="0"/><w:jc w:val="center"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr><w:b/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:keepNext/><w:ind w:firstLine="0"/><w:outlineLvl w:val="1"/></w:pPr><w:rPr><w:b/></w:rPr></w:style>
+</w:styles>
+"#;
+
+fn docx_run(text: &str, bold: bool, italic: bool) -> String {
+    let props = match (bold, italic) {
+        (false, false) => String::new(),
+        _ => format!("<w:rPr>{}{}</w:rPr>", if bold { "<w:b/>" } else { "" }, if italic { "<w:i/>" } else { "" }),
+    };
+    format!("<w:r>{props}<w:t xml:space=\"preserve\">{}</w:t></w:r>", html_escape(text))
+}
+
+fn docx_paragraph(style: Option<&str>, runs: &str) -> String {
+    let props = style.map(|s| format!("<w:pPr><w:pStyle w:val=\"{s}\"/></w:pPr>")).unwrap_or_default();
+    format!("<w:p>{props}{runs}</w:p>\n")
+}
+
+// Body paragraphs for one document: markdown paragraphs (blank-line separated)
+// become Normal paragraphs with bold/italic runs kept, headings inside the
+// body become Heading2, and rules become a centered `#` scene break.
+fn markdown_to_docx(md: &str) -> String {
+    use pulldown_cmark::{Event, Tag, TagEnd};
+
+    let mut out = String::new();
+    let mut runs = String::new();
+    let mut style: Option<&str> = None;
+    let (mut bold, mut italic) = (0u32, 0u32);
+    for event in pulldown_cmark::Parser::new(md) {
+        match event {
+            Event::Start(Tag::Heading { .. }) => style = Some("Heading2"),
+            Event::Start(Tag::Strong) => bold += 1,
+            Event::Start(Tag::Emphasis) => italic += 1,
+            Event::End(TagEnd::Strong) => bold = bold.saturating_sub(1),
+            Event::End(TagEnd::Emphasis) => italic = italic.saturating_sub(1),
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item | TagEnd::CodeBlock) => {
+                if !runs.is_empty() {
+                    out.push_str(&docx_paragraph(style, &runs));
+                    runs.clear();
+                }
+                style = None;
+            }
+            Event::Text(t) | Event::Code(t) | Event::Html(t) | Event::InlineHtml(t) => {
+                runs.push_str(&docx_run(&t, bold > 0, italic > 0));
+            }
+            Event::SoftBreak => runs.push_str(&docx_run(" ", bold > 0, italic > 0)),
+            Event::HardBreak => runs.push_str("<w:r><w:br/></w:r>"),
+            Event::Rule => {
+                out.push_str("<w:p><w:pPr><w:ind w:firstLine=\"0\"/><w:jc w:val=\"center\"/></w:pPr>");
+                out.push_str(&docx_run("#", false, false));
+                out.push_str("</w:p>\n");
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Export the manuscript as a Word document for submissions: each live
+/// document, in tree order, becomes a chapter under a Heading1 of its title.
+/// Returns the written path.
+#[tauri::command]
+pub fn export_docx(state: State<'_, AppState>, project_path: String, dest_path: String) -> Result<String, AppError> {
+    state.begin_operation();
+    let conn = state.conn(&project_path)?;
+    let mut docs = Vec::new();
+    manuscript_docs(&conn, None, &mut docs)?;
+
+    let mut body = String::new();
+    for (i, (title, md)) in docs.iter().enumerate() {
+        // Heading1 breaks the page; the first chapter needn't open on a blank one.
+        let heading_props = if i == 0 {
+            "<w:pPr><w:pStyle w:val=\"Heading1\"/><w:pageBreakBefore w:val=\"0\"/></w:pPr>"
+        } else {
+            "<w:pPr><w:pStyle w:val=\"Heading1\"/></w:pPr>"
+        };
+        body.push_str(&format!("<w:p>{heading_props}{}</w:p>\n", docx_run(title, false, false)));
+        body.push_str(&markdown_to_docx(md));
+    }
+    let document = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <w:document xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">\n<w:body>\n{body}\
+         <w:sectPr><w:pgSz w:w=\"12240\" w:h=\"15840\"/>\
+         <w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" w:header=\"720\" w:footer=\"720\" w:gutter=\"0\"/></w:sectPr>\n\
+         </w:body>\n</w:document>\n"
+    );
+
+    let entries = [
+        ("[Content_Types].xml", DOCX_CONTENT_TYPES),
+        ("_rels/.rels", DOCX_PACKAGE_RELS),
+        ("word/_rels/document.xml.rels", DOCX_DOCUMENT_RELS),
+        ("word/styles.xml", DOCX_STYLES),
+        ("word/document.xml", document.as_str()),
+    ]
+    .map(|(name, xml)| (name.to_string(), xml.to_string()));
+    write_zip_package(&state, &dest_path, &entries)?;
+    Ok(dest_path)
+}
+
+// ------- PDF (written by hand like the docx/epub packages: the standard
+// Helvetica fonts need no embedding, so a page is just positioned text)
+
+const PDF_PAGE_WIDTH: f32 = 612.0; // US Letter, in points
+const PDF_PAGE_HEIGHT: f32 = 792.0;
+const PDF_MARGIN: f32 = 72.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum PdfStyle {
+    Title,
+    Heading(u8),
+    Body,
+    SceneBreak,
+}
+
+impl PdfStyle {
+    // (font resource, size in points); F1 is Helvetica, F2 Helvetica-Bold.
+    fn font(self) -> (&'static str, f32) {
+        match self {
+            PdfStyle::Title => ("F2", 20.0),
+            PdfStyle::Heading(1) => ("F2", 17.0),
+            PdfStyle::Heading(2) => ("F2", 15.0),
+            PdfStyle::Heading(_) => ("F2", 13.0),
+            PdfStyle::Body | PdfStyle::SceneBreak => ("F1", 12.0),
+        }
+    }
+}
+
+// Helvetica advance widths in 1/1000 em for ASCII 32..=126 (Adobe's AFM).
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, // space../
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, // 0..9
+    278, 278, 584, 584, 584, 556, 1015, // :..@
+    667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944,
+    667, 667, 611, // A..Z
+    278, 278, 278, 469, 556, 333, // [..`
+    556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722,
+    500, 500, 500, // a..z
+    334, 260, 334, 584, // {..~
+];
+
+// WinAnsiEncoding (Windows-1252) is all the standard fonts offer; anything
+// outside it prints as '?'.
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        '\u{a0}'..='\u{ff}' => c as u32 as u8,
+        '€' => 0x80,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        _ => b'?',
+    }
+}
+
+// Width of `text` in points. Bold is a little wider than regular; scaling the
+// regular metrics errs towards wrapping early rather than running into the margin.
+fn pdf_text_width(text: &str, font: &str, size: f32) -> f32 {
+    let units: u32 = text
+        .chars()
+        .map(|c| match win_ansi(c) {
+            b @ 32..=126 => HELVETICA_WIDTHS[(b - 32) as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    let scale = if font == "F2" { 1.08 } else { 1.0 };
+    units as f32 * scale * size / 1000.0
+}
+
+// Greedy word wrap to `max` points; `\n` (hard breaks, code lines) always
+// starts a new line and a word too long for a line is split wherever it overflows.
+fn pdf_wrap(text: &str, font: &str, size: f32, max: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for source_line in text.split('\n') {
+        let mut line = String::new();
+        for word in source_line.split_whitespace() {
+            let candidate = if line.is_empty() { word.to_string() } else { format!("{line} {word}") };
+            if pdf_text_width(&candidate, font, size) <= max {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for c in word.chars() {
+                line.push(c);
+                if line.chars().count() > 1 && pdf_text_width(&line, font, size) > max {
+                    line.pop();
+                    lines.push(std::mem::replace(&mut line, c.to_string()));
+                }
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// Headings, paragraphs, list items (bulleted) and code blocks as plain text
+// blocks; rules become a centered `#` scene break, as in the docx export.
+fn markdown_to_pdf_blocks(md: &str) -> Vec<(PdfStyle, String)> {
+    use pulldown_cmark::{Event, Tag, TagEnd};
+
+    let mut blocks = Vec::new();
+    let mut text = String::new();
+    let mut style = PdfStyle::Body;
+    let flush = |blocks: &mut Vec<(PdfStyle, String)>, text: &mut String, style: PdfStyle| {
+        let t = text.trim_end();
+        if !t.is_empty() && t != "•" {
+            blocks.push((style, t.to_string()));
+        }
+        text.clear();
+    };
+    for event in pulldown_cmark::Parser::new(md) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => style = PdfStyle::Heading(level as u8),
+            Event::Start(Tag::Item) => {
+                flush(&mut blocks, &mut text, style);
+                text.push_str("• ");
+            }
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item | TagEnd::CodeBlock) => {
+                flush(&mut blocks, &mut text, style);
+                style = PdfStyle::Body;
+            }
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::Rule => blocks.push((PdfStyle::SceneBreak, "#".into())),
+            _ => {}
+        }
+    }
+    flush(&mut blocks, &mut text, style);
+    blocks
+}
+
+// A PDF literal string (without the parens) for WinAnsi-encoded `text`.
+fn pdf_string(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match win_ansi(c) {
+            b @ (b'(' | b')' | b'\\') => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            b @ 32..=126 => out.push(b as char),
+            b => out.push_str(&format!("\\{b:03o}")),
+        }
+    }
+    out
+}
+
+// Lay the title and blocks out onto pages; returns one content stream per
+// page, each with its page number centered in the bottom margin.
+fn pdf_pages(title: &str, blocks: &[(PdfStyle, String)]) -> Vec<String> {
+    let top = PDF_PAGE_HEIGHT - PDF_MARGIN;
+    let max_width = PDF_PAGE_WIDTH - 2.0 * PDF_MARGIN;
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut y = top;
+
+    let all = std::iter::once((PdfStyle::Title, title.to_string())).chain(blocks.iter().cloned());
+    for (style, text) in all {
+        let (font, size) = style.font();
+        let leading = size * 1.4;
+        if matches!(style, PdfStyle::Heading(_)) {
+            // Space above, and don't strand a heading at the foot of a page.
+            if y < top {
+                y -= size * 0.6;
+            }
+            if y - leading * 3.0 < PDF_MARGIN && !page.is_empty() {
+                pages.push(std::mem::take(&mut page));
+                y = top;
+            }
+        }
+        for line in pdf_wrap(&text, font, size, max_width) {
+            if y - leading < PDF_MARGIN {
+                pages.push(std::mem::take(&mut page));
+                y = top;
+            }
+            y -= leading;
+            let x = match style {
+                PdfStyle::Title | PdfStyle::SceneBreak => (PDF_PAGE_WIDTH - pdf_text_width(&line, font, size)) / 2.0,
+                _ => PDF_MARGIN,
+            };
+            page.push_str(&format!("BT /{font} {size} Tf {x:.2} {y:.2} Td ({}) Tj ET\n", pdf_string(&line)));
+        }
+        y -= size * 0.5;
+    }
+    pages.push(page);
+
+    for (i, page) in pages.iter_mut().enumerate() {
+        let number = (i + 1).to_string();
+        let x = (PDF_PAGE_WIDTH - pdf_text_width(&number, "F1", 10.0)) / 2.0;
+        page.push_str(&format!("BT /F1 10 Tf {x:.2} {:.2} Td ({number}) Tj ET\n", PDF_MARGIN / 2.0));
+    }
+    pages
+}
+
+// Assemble the file: catalog, page tree, the two fonts, then a page object
+// and content stream per page, with the cross-reference table at the end.
+fn pdf_document(pages: &[String]) -> Vec<u8> {
+    let font = |name: &str| format!("<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>");
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 5 + 2 * i)).collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()),
+        font("Helvetica"),
+        font("Helvetica-Bold"),
+    ];
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PDF_PAGE_WIDTH} {PDF_PAGE_HEIGHT}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            6 + 2 * i
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{content}endstream", content.len()));
+    }
+
+    let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, body) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n{body}\nendobj\n", i + 1).as_bytes());
+    }
+    let xref = out.len();
+    let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for off in offsets {
+        table.push_str(&format!("{off:010} 00000 n \n"));
+    }
+    table.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n", objects.len() + 1));
+    out.extend_from_slice(table.as_bytes());
+    out
+}
+
+/// Render one document to a PDF at `dest_path` for quick sharing: its title,
+/// then headings and paragraphs (lists and code as plain text) on numbered US
+/// Letter pages. Uses the built-in Helvetica, so characters outside
+/// Windows-1252 come out as `?`. Returns the written path.
+#[tauri::command]
+pub fn export_document_pdf(state: State<'_, AppState>, project_path: String, doc_id: String, dest_path: String) -> Result<String, AppError> {
+    let conn = state.conn(&project_path)?;
+
+    let (title, md): (String, String) = conn
+        .query_row(
+            "SELECT Document.title, Body.markdown
+             FROM Document JOIN Body ON Body.document_id = Document.id
+             WHERE Document.id=?",
+            [doc_id],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("document does not exist".into()))?;
+
+    let pages = pdf_pages(&title, &markdown_to_pdf_blocks(&md));
+    atomic_write(Path::new(&dest_path), &pdf_document(&pages))?;
+    Ok(dest_path)
+}
+
+// Field order for the markdown character sheet.
+const CHARACTER_SHEET_FIELDS: &[(&str, &str)] = &[
+    ("age", "Age"),
+    ("nationality", "Nationality"),
+    ("sexuality", "Sexuality"),
+    ("height", "Height"),
+];
+
+/// Write a character sheet to `dest_path`. `format` is "json" (full record with
+/// parsed attributes and the character's image files) or "md" (readable sheet).
+#[tauri::command]
+pub fn export_character(state: State<'_, AppState>, project_path: String, char_id: String, dest_path: String, format: String) -> Result<String, AppError> {
+    let conn = state.conn(&project_path)?;
+    let mut record = select_character(&conn, &char_id)?;
+
+    let attributes: Vec<serde_json::Value> = record["attributes"].as_array().cloned().unwrap_or_default();
+
+    let out = match format.as_str() {
+        "json" => {
+            let assets = Path::new(&project_path).join("assets").join("characters").join(&char_id);
+            let mut images: Vec<String> = fs::read_dir(&assets)
+                .map(|rd| {
+                    rd.filter_map(Result::ok)
+                        .filter(|e| e.path().is_file())
+                        .map(|e| e.file_name().to_string_lossy().into_owned())
+                        .collect()
+                })
+                .unwrap_or_default();
+            images.sort();
+            record["images"] = serde_json::json!(images);
+            serde_json::to_string_pretty(&record).unwrap_or_default()
+        }
+        "md" => {
+            let field = |k: &str| record[k].as_str().unwrap_or("").to_string();
+            let mut md = format!("# {}\n\n", field("name"));
+            for (key, label) in CHARACTER_SHEET_FIELDS {
+                let v = field(key);
+                if !v.is_empty() {
+                    md.push_str(&format!("- **{label}:** {v}\n"));
+                }
+            }
+            for a in &attributes {
+                let k = a["key"].as_str().unwrap_or("");
+                let v = a["value"].as_str().unwrap_or("");
+                if !k.is_empty() || !v.is_empty() {
+                    md.push_str(&format!("- **{k}:** {v}\n"));
+                }
+            }
+            md
+        }
+        other => return Err(AppError::Validation(format!("unsupported export format: {other}"))),
+    };
+
+    atomic_write(Path::new(&dest_path), out.as_bytes())?;
+    Ok(dest_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh, migrated project in a temp dir and a connection to it.
+    fn project() -> (tempfile::TempDir, String, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().into_owned();
+        let mut conn = open_conn(&path, false).unwrap();
+        run_migrations(&mut conn).unwrap();
+        (dir, path, conn)
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn failed_folder_delete_leaves_rows_and_files() {
+        let (_dir, path, mut conn) = project();
+        conn.execute_batch(
+            "INSERT INTO Folder(id, name) VALUES('f1', 'A');
+             INSERT INTO Folder(id, parent_id, name) VALUES('f2', 'f1', 'B');
+             INSERT INTO Character(id, project_id, folder_id, name) VALUES('c1', 'p1', 'f2', 'C');",
+        )
+        .unwrap();
+        let doc = insert_document(&mut conn, &path, "Doc", Some("f2"), "body").unwrap();
+        let mirror = mirror_path(&conn, &path, &doc).unwrap();
+        let asset = Path::new(&path).join("assets").join("characters").join("c1").join("a.png");
+        fs::create_dir_all(asset.parent().unwrap()).unwrap();
+        fs::write(&asset, b"img").unwrap();
+        assert!(mirror.is_file());
+
+        // The folder rows go last, so this fails after the docs and characters are deleted.
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER fail_folder_delete BEFORE DELETE ON Folder
+             BEGIN SELECT RAISE(ABORT, 'injected failure'); END;",
+        )
+        .unwrap();
+        assert!(delete_folder_tree(&mut conn, &path, "f1").is_err());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM Folder"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM Document"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM Character"), 1);
+        assert!(mirror.is_file());
+        assert!(asset.is_file());
+
+        conn.execute_batch("DROP TRIGGER fail_folder_delete;").unwrap();
+        delete_folder_tree(&mut conn, &path, "f1").unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM Folder"), 0);
+        assert!(!mirror.exists());
+        assert!(!asset.exists());
+    }
+
+    #[test]
+    fn folder_cannot_move_into_its_own_subtree() {
+        let (_dir, _path, conn) = project();
+        conn.execute_batch(
+            "INSERT INTO Folder(id, name) VALUES('a', 'A');
+             INSERT INTO Folder(id, parent_id, name) VALUES('b', 'a', 'B');
+             INSERT INTO Folder(id, parent_id, name) VALUES('c', 'b', 'C');",
+        )
+        .unwrap();
+        let parent_of_a = || -> Option<String> {
+            conn.query_row("SELECT parent_id FROM Folder WHERE id='a'", [], |r| r.get(0)).unwrap()
+        };
+
+        let err = reparent_folder(&conn, "a", Some("c")).unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)), "{err}");
+        assert_eq!(parent_of_a(), None);
+        assert!(matches!(reparent_folder(&conn, "a", Some("a")), Err(AppError::Validation(_))));
+        assert!(matches!(reparent_folder(&conn, "ghost", None), Err(AppError::NotFound(_))));
+
+        reparent_folder(&conn, "c", None).unwrap();
+        reparent_folder(&conn, "a", Some("c")).unwrap();
+        assert_eq!(parent_of_a().as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn word_count_ignores_markup() {
+        let md = "# Chapter One\n\nShe read the [old map](https://example.com/map) twice.\n\n## **Part** _two_\n";
+        // Chapter One / She read the old map twice / Part two
+        assert_eq!(text_stats(md).0, 10);
+        assert!(!strip_markdown(md).contains("example.com"));
+        assert!(!strip_markdown(md).contains('#'));
+    }
+
+    #[test]
+    fn backup_zip_includes_character_images() {
+        let (_dir, path, conn) = project();
+        conn.execute("INSERT INTO Character(id, project_id, name) VALUES('c1', 'p1', 'Ada')", []).unwrap();
+        let asset = Path::new(&path).join("assets").join("characters").join("c1").join("a.png");
+        fs::create_dir_all(asset.parent().unwrap()).unwrap();
+        fs::write(&asset, b"img").unwrap();
+
+        fs::create_dir_all(Path::new(&path).join("backups")).unwrap();
+
+        let state = AppState::default();
+        write_backup(&state, &path, false, None, &|_, _| {}).unwrap();
+        let (_, name, _) = backup_entries(&path).unwrap().remove(0);
+        let archive = open_backup(&Path::new(&path).join("backups").join(name)).unwrap();
+        let entries: Vec<&str> = archive.file_names().collect();
+        assert!(entries.contains(&"assets/characters/c1/a.png"), "{entries:?}");
+        assert!(entries.contains(&"project.db"));
+    }
+
+    #[test]
+    fn backup_appears_only_once_complete() {
+        let (_dir, path, mut conn) = project();
+        insert_document(&mut conn, &path, "One", None, "first").unwrap();
+        insert_document(&mut conn, &path, "Two", None, "second").unwrap();
+        let backups = Path::new(&path).join("backups");
+        fs::create_dir_all(&backups).unwrap();
+        let listing = || -> Vec<String> {
+            let mut names: Vec<String> =
+                fs::read_dir(&backups).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+            names.sort();
+            names
+        };
+
+        // Cancel partway: only the .tmp exists mid-write, and nothing is left after.
+        let state = AppState::default();
+        let progress = |done: usize, _: usize| {
+            assert!(listing().iter().all(|n| n.ends_with(".zip.tmp")), "{:?}", listing());
+            if done == 1 {
+                state.request_cancel();
+            }
+        };
+        assert!(matches!(write_backup(&state, &path, false, None, &progress), Err(AppError::Cancelled)));
+        assert!(listing().is_empty(), "{:?}", listing());
+
+        write_backup(&state, &path, false, None, &|_, _| {}).unwrap();
+        let names = listing();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].ends_with(".zip"));
+        open_backup(&backups.join(&names[0])).unwrap();
+    }
+
+    #[test]
+    fn cancelled_package_leaves_no_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("out").join("book.epub");
+        let dest_path = dest.to_string_lossy().into_owned();
+        let entries = vec![("mimetype".to_string(), "application/epub+zip".to_string())];
+
+        let state = AppState::default();
+        state.request_cancel();
+        assert!(matches!(write_zip_package(&state, &dest_path, &entries), Err(AppError::Cancelled)));
+        assert!(fs::read_dir(dest.parent().unwrap()).unwrap().next().is_none());
+
+        state.begin_operation();
+        write_zip_package(&state, &dest_path, &entries).unwrap();
+        assert!(dest.is_file());
+        assert!(!Path::new(&format!("{dest_path}.tmp")).exists());
+    }
+
+    #[test]
+    fn unchanged_save_keeps_mirror_mtime() {
+        let (_dir, path, mut conn) = project();
+        let doc = insert_document(&mut conn, &path, "Doc", None, "same text").unwrap();
+        let mirror = mirror_path(&conn, &path, &doc).unwrap();
+        // Backdate the mirror so any rewrite would show up without sleeping.
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        fs::File::options().write(true).open(&mirror).unwrap().set_modified(old).unwrap();
+        let mtime = || fs::metadata(&mirror).unwrap().modified().unwrap();
+
+        mirror_md(&conn, &path, &doc, "same text").unwrap();
+        assert_eq!(mtime(), old);
+
+        conn.execute("UPDATE Body SET markdown='new text' WHERE document_id=?", [&doc]).unwrap();
+        mirror_md(&conn, &path, &doc, "new text").unwrap();
+        assert_ne!(mtime(), old);
+    }
+
+    #[test]
+    fn project_names_must_be_one_path_component() {
+        for bad in ["..", ".", "", "   ", "a/b", "../escape", "a\\b", "CON", "nul.txt", "trailing."] {
+            assert!(matches!(validate_project_name(bad), Err(AppError::Validation(_))), "{bad:?} accepted");
+        }
+        assert_eq!(validate_project_name("  My Novel  ").unwrap(), "My Novel");
+        assert_eq!(validate_project_name("v1.2 draft").unwrap(), "v1.2 draft");
+    }
+
+    #[test]
+    fn read_only_project_can_be_backed_up() {
+        let (_dir, path, mut conn) = project();
+        insert_document(&mut conn, &path, "Doc", None, "text").unwrap();
+        drop(conn);
+
+        let state = AppState::default();
+        state.open(&path, true).unwrap();
+        write_backup(&state, &path, false, None, &|_, _| {}).unwrap();
+        assert_eq!(backup_entries(&path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn incremental_backups_verify_and_prune_through_their_base() {
+        let (_dir, path, mut conn) = project();
+        let kept = insert_document(&mut conn, &path, "Kept", None, "same").unwrap();
+        let edited = insert_document(&mut conn, &path, "Edited", None, "before").unwrap();
+        let backups = Path::new(&path).join("backups");
+        let state = AppState::default();
+        let backup_as = |incremental: bool, name: &str| {
+            write_backup(&state, &path, incremental, None, &|_, _| {}).unwrap();
+            // Backdate it so each backup gets its own name within the same second.
+            let (_, fresh, _) =
+                backup_entries(&path).unwrap().into_iter().find(|(_, n, _)| !n.starts_with("backup_2020")).unwrap();
+            fs::rename(backups.join(fresh), backups.join(name)).unwrap();
+            backups.join(name)
+        };
+
+        backup_as(false, "backup_20200101_000000.zip");
+        let base = backup_as(false, "backup_20200101_000001.zip");
+        fs::write(mirror_path(&conn, &path, &edited).unwrap(), "after").unwrap();
+        let incr = backup_as(true, "backup_20200101_000002_incr.zip");
+
+        let names: Vec<String> = open_backup(&incr).unwrap().file_names().map(str::to_string).collect();
+        assert!(!names.contains(&"project.db".to_string()), "{names:?}");
+        let kept_rel = mirror_path(&conn, &path, &kept).unwrap().strip_prefix(&path).unwrap().to_string_lossy().replace('\\', "/");
+        assert!(!names.contains(&kept_rel));
+
+        let report = verify_backup(incr.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(report["valid"], true, "{report}");
+        assert_eq!(report["dbOk"], true);
+        assert_eq!(report["mdFiles"], 2);
+
+        // Keeping only the incremental keeps its base too.
+        assert_eq!(prune_backup_files(&path, 1).unwrap(), 1);
+        assert!(base.is_file());
+        assert!(!backups.join("backup_20200101_000000.zip").exists());
+
+        fs::remove_file(&base).unwrap();
+        let report = verify_backup(incr.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(report["valid"], false);
+        assert!(report["issues"][0].as_str().unwrap().contains("missing"), "{report}");
+    }
+
+    #[test]
+    fn undo_rows_keep_blobs_intact() {
+        let (_dir, _path, conn) = project();
+        let bytes: Vec<u8> = vec![0x00, 0xff, 0xfe, 0x80, b'a'];
+        conn.execute_batch("CREATE TEMP TABLE Blobs(id TEXT PRIMARY KEY, data BLOB)").unwrap();
+        conn.execute("INSERT INTO Blobs(id, data) VALUES('b1', ?)", [&bytes]).unwrap();
+
+        let captured = capture_rows(&conn, "Blobs", "id IN ({ids})", &["b1".to_string()]).unwrap();
+        // Through JSON, as the UndoLog stores it.
+        let captured: UndoTable = serde_json::from_str(&serde_json::to_string(&captured).unwrap()).unwrap();
+        conn.execute("DELETE FROM Blobs", []).unwrap();
+        restore_rows(&conn, &captured).unwrap();
+
+        let back: Vec<u8> = conn.query_row("SELECT data FROM Blobs WHERE id='b1'", [], |r| r.get(0)).unwrap();
+        assert_eq!(back, bytes);
+    }
+
+    #[test]
+    fn folder_undo_lists_parents_first() {
+        let (_dir, path, mut conn) = project();
+        // Children get lower rowids than their parents.
+        conn.execute_batch(
+            "INSERT INTO Folder(id, parent_id, name) VALUES('c', 'b', 'C');
+             INSERT INTO Folder(id, parent_id, name) VALUES('b', 'a', 'B');
+             INSERT INTO Folder(id, name) VALUES('a', 'A');",
+        )
+        .unwrap();
+        delete_folder_tree(&mut conn, &path, "a").unwrap();
+
+        let json: String = conn.query_row("SELECT payload FROM UndoLog", [], |r| r.get(0)).unwrap();
+        let payload: UndoPayload = serde_json::from_str(&json).unwrap();
+        let folders = payload.tables.iter().find(|t| t.table == "Folder").unwrap();
+        let order: Vec<&str> = folders.rows.iter().map(|r| r["id"].as_str().unwrap()).collect();
+        assert_eq!(order, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn forced_restore_replaces_an_open_project() {
+        let (_dir, path, mut conn) = project();
+        insert_document(&mut conn, &path, "One", None, "kept").unwrap();
+        drop(conn);
+        let state = AppState::default();
+        write_backup(&state, &path, false, None, &|_, _| {}).unwrap();
+        let (_, name, _) = backup_entries(&path).unwrap().remove(0);
+
+        // A later edit, still sitting in the open pool's WAL.
+        insert_document(&mut state.conn(&path).unwrap(), &path, "Two", None, "lost").unwrap();
+        let wal = Path::new(&path).join("project.db-wal");
+        assert!(wal.is_file());
+
+        let backup = Path::new(&path).join("backups").join(name);
+        assert!(matches!(restore_backup(&state, &backup, &path, false), Err(AppError::Validation(_))));
+        restore_backup(&state, &backup, &path, true).unwrap();
+        assert!(!wal.exists());
+        let docs: i64 = state.conn(&path).unwrap().query_row("SELECT COUNT(*) FROM Document", [], |r| r.get(0)).unwrap();
+        assert_eq!(docs, 1);
+    }
+
+    #[test]
+    fn same_stem_images_get_their_own_thumbnails() {
+        let (dir, path, _conn) = project();
+        let (png, jpg) = (dir.path().join("a.png"), dir.path().join("a.jpg"));
+        image::RgbImage::from_pixel(4, 4, image::Rgb([255, 0, 0])).save(&png).unwrap();
+        image::RgbImage::from_pixel(4, 4, image::Rgb([0, 0, 255])).save(&jpg).unwrap();
+
+        let (_, png_thumb) = import_image_file(&path, "c1", &png.to_string_lossy()).unwrap();
+        let (_, jpg_thumb) = import_image_file(&path, "c1", &jpg.to_string_lossy()).unwrap();
+        assert_ne!(png_thumb, jpg_thumb);
+        let red = image::open(&png_thumb).unwrap().to_rgb8();
+        assert_eq!(red.get_pixel(0, 0).0, [255, 0, 0]);
+        assert!(jpg_thumb.is_file());
+    }
+
+    #[test]
+    fn image_import_stays_inside_the_character_dir() {
+        let (dir, path, _conn) = project();
+        let img = dir.path().join("a.png");
+        image::RgbImage::new(4, 4).save(&img).unwrap();
+        let src = img.to_string_lossy();
+
+        for bad in ["..", "../../x", "a/b", ""] {
+            assert!(matches!(import_image_file(&path, bad, &src), Err(AppError::Validation(_))), "{bad:?} accepted");
+        }
+        #[cfg(unix)]
+        {
+            let chars = Path::new(&path).join("assets").join("characters");
+            fs::create_dir_all(&chars).unwrap();
+            std::os::unix::fs::symlink(dir.path(), chars.join("evil")).unwrap();
+            let err = import_image_file(&path, "evil", &src).err().unwrap();
+            assert!(err.to_string().contains("escapes"), "{err}");
+        }
+    }
+
+    #[test]
+    fn character_delete_cascades_its_relations() {
+        let (_dir, path, conn) = project();
+        conn.execute_batch(
+            "INSERT INTO Character(id, project_id, name) VALUES('a', 'p1', 'A');
+             INSERT INTO Character(id, project_id, name) VALUES('b', 'p1', 'B');
+             INSERT INTO Character(id, project_id, name) VALUES('c', 'p1', 'C');
+             INSERT INTO CharacterRelation(id, from_char, to_char, kind) VALUES('r1', 'a', 'b', 'ally');
+             INSERT INTO CharacterRelation(id, from_char, to_char, kind) VALUES('r2', 'c', 'a', 'rival');
+             INSERT INTO CharacterRelation(id, from_char, to_char, kind) VALUES('r3', 'b', 'c', 'sibling');",
+        )
+        .unwrap();
+        delete_character_internal(&conn, &path, "a").unwrap();
+        let left: Vec<String> = conn
+            .prepare("SELECT id FROM CharacterRelation ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(left, ["r3"]);
+    }
+
+    #[test]
+    fn character_delete_never_reaches_outside_its_asset_dir() {
+        let (_dir, path, conn) = project();
+        conn.execute("INSERT INTO Character(id, project_id, name) VALUES('../..', 'p1', 'Evil')", []).unwrap();
+        for bad in ["../..", "..", "a/b", ""] {
+            assert!(matches!(delete_character_internal(&conn, &path, bad), Err(AppError::Validation(_))), "{bad:?}");
+        }
+        assert!(matches!(delete_character_internal(&conn, &path, "ghost"), Err(AppError::NotFound(_))));
+
+        // Whatever a caller hands it, remove_paths leaves the project's vitals alone.
+        let root = Path::new(&path);
+        fs::create_dir_all(root.join("md")).unwrap();
+        let vital = [root.to_path_buf(), root.join("md"), root.join("project.db"), root.join("assets/characters/../../md")];
+        remove_paths(&path, &vital);
+        assert!(root.join("project.db").is_file());
+        assert!(root.join("md").is_dir());
+    }
+
+    #[test]
+    fn character_search_skips_the_trash() {
+        let (_dir, _path, conn) = project();
+        conn.execute_batch(
+            "INSERT INTO Character(id, project_id, name, nationality, attributes) VALUES('a', 'p1', 'Mara', 'Dutch', '[]');
+             INSERT INTO Character(id, project_id, name, nationality, attributes)
+                 VALUES('b', 'p1', 'Ines', '', '[{\"key\":\"home\",\"value\":\"Maranhão\"}]');
+             INSERT INTO Character(id, project_id, name, nationality, attributes, deleted_at)
+                 VALUES('c', 'p1', 'Marek', '', '[]', CURRENT_TIMESTAMP);",
+        )
+        .unwrap();
+        let hits = find_characters(&conn, "mar").unwrap();
+        let found: Vec<(&str, &str)> =
+            hits.iter().map(|h| (h["id"].as_str().unwrap(), h["matchedField"].as_str().unwrap())).collect();
+        assert_eq!(found, [("b", "attributes"), ("a", "name")]);
+        assert!(find_characters(&conn, "%").unwrap().is_empty());
+    }
+
+    #[test]
+    fn regex_search_skips_empty_matches() {
+        let md = "baa b aaa";
+        let found = |pattern: &str| -> Vec<String> {
+            let re = regex::Regex::new(pattern).unwrap();
+            regex_matches(&re, md).map(|m| m.as_str().to_string()).collect()
+        };
+        assert_eq!(found("a*"), ["aa", "aaa"]);
+        assert!(found("^").is_empty());
+        assert_eq!(found("b"), ["b", "b"]);
+    }
+}
@@ -1,54 +1,458 @@
-use rusqlite::{Connection, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags, Result, Transaction};
+
+use crate::commands::{CharacterSummary, Doc, Folder};
+use crate::error::AppError;
+
+pub type Pool = r2d2::Pool<SqliteConnectionManager>;
+pub type PooledConn = r2d2::PooledConnection<SqliteConnectionManager>;
+
+// Applied to every connection: WAL + busy_timeout so autosave and manual
+// saves don't trip "database is locked", and foreign_keys so ON DELETE
+// CASCADE actually fires.
+const CONN_PRAGMAS: &str = "PRAGMA journal_mode=WAL;
+     PRAGMA busy_timeout=5000;
+     PRAGMA foreign_keys=ON;";
+
+// Read-only connections can't switch journal mode; query_only is a second
+// guard on top of the open flags.
+const READONLY_PRAGMAS: &str = "PRAGMA busy_timeout=5000;
+     PRAGMA foreign_keys=ON;
+     PRAGMA query_only=ON;";
+
+fn open_flags(readonly: bool) -> OpenFlags {
+    if readonly {
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX
+    } else {
+        OpenFlags::default()
+    }
+}
+
+fn init_sql(readonly: bool) -> &'static str {
+    if readonly { READONLY_PRAGMAS } else { CONN_PRAGMAS }
+}
+
+/// Open `PROJECT/project.db` directly, outside the pool. Used for the first
+/// open of a project (create/restore) before a pool makes sense.
+pub fn open_conn(project_path: impl AsRef<Path>, readonly: bool) -> std::result::Result<Connection, AppError> {
+    let dbp = project_path.as_ref().join("project.db");
+    let conn = Connection::open_with_flags(&dbp, open_flags(readonly))?;
+    conn.execute_batch(init_sql(readonly))?;
+    Ok(conn)
+}
+
+struct ProjectPool {
+    pool: Pool,
+    readonly: bool,
+}
+
+/// A running writing session: each document's word count when it started,
+/// and as of its latest save during the session.
+pub struct ActiveSession {
+    pub id: String,
+    pub baseline: HashMap<String, usize>,
+    pub latest: HashMap<String, usize>,
+}
+
+impl ActiveSession {
+    /// Net words added so far; documents created mid-session start from 0.
+    pub fn words_added(&self) -> i64 {
+        self.latest
+            .iter()
+            .map(|(id, &words)| words as i64 - self.baseline.get(id).copied().unwrap_or(0) as i64)
+            .sum()
+    }
+}
+
+/// Tauri-managed state: one connection pool per opened project, the cancel
+/// flag polled by long-running commands, and any running writing session.
+#[derive(Default)]
+pub struct AppState {
+    pools: Mutex<HashMap<PathBuf, ProjectPool>>,
+    cancel: AtomicBool,
+    sessions: Mutex<HashMap<PathBuf, ActiveSession>>,
+}
+
+impl AppState {
+    /// Borrow a pooled connection for `project_path`, creating a read-write
+    /// pool on first use.
+    pub fn conn(&self, project_path: &str) -> std::result::Result<PooledConn, AppError> {
+        let key = PathBuf::from(project_path);
+        let pool = {
+            // A poisoned lock only means another command panicked; the map is still fine.
+            let mut pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+            match pools.get(&key) {
+                Some(entry) => entry.pool.clone(),
+                None => {
+                    let pool = build_pool(&key, false)?;
+                    pools.insert(key, ProjectPool { pool: pool.clone(), readonly: false });
+                    pool
+                }
+            }
+        };
+        Ok(pool.get()?)
+    }
+
+    /// Like `conn`, for commands that write to the project; refuses if the
+    /// project was opened read-only.
+    pub fn conn_mut(&self, project_path: &str) -> std::result::Result<PooledConn, AppError> {
+        self.ensure_writable(project_path)?;
+        self.conn(project_path)
+    }
+
+    /// For commands that write project files without going through the db.
+    pub fn ensure_writable(&self, project_path: &str) -> std::result::Result<(), AppError> {
+        if self.is_readonly(project_path) {
+            return Err(AppError::Validation("project is open read-only".into()));
+        }
+        Ok(())
+    }
+
+    /// Whether `project_path` was opened read-only.
+    pub fn is_readonly(&self, project_path: &str) -> bool {
+        let pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+        pools.get(Path::new(project_path)).is_some_and(|e| e.readonly)
+    }
+
+    /// (Re)open the pool for `project_path` in the given mode, replacing one
+    /// opened in the other mode.
+    pub fn open(&self, project_path: &str, readonly: bool) -> std::result::Result<(), AppError> {
+        let key = PathBuf::from(project_path);
+        let mut pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+        if pools.get(&key).is_some_and(|e| e.readonly == readonly) {
+            return Ok(());
+        }
+        let pool = build_pool(&key, readonly)?;
+        pools.insert(key, ProjectPool { pool, readonly });
+        Ok(())
+    }
+
+    /// Drop the pool for `project_path`; the next `conn` opens a fresh one.
+    pub fn close(&self, project_path: &str) {
+        let mut pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+        pools.remove(Path::new(project_path));
+    }
+
+    /// Start tracking `session` for `project_path`; refused if one is running.
+    pub fn begin_writing_session(&self, project_path: &str, session: ActiveSession) -> std::result::Result<(), AppError> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let key = PathBuf::from(project_path);
+        if sessions.contains_key(&key) {
+            return Err(AppError::Conflict("a writing session is already running".into()));
+        }
+        sessions.insert(key, session);
+        Ok(())
+    }
+
+    pub fn finish_writing_session(&self, project_path: &str) -> Option<ActiveSession> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.remove(Path::new(project_path))
+    }
+
+    /// Record `doc_id`'s word count after a save. With a session running,
+    /// returns its id and updated net words added; `words` is only called then.
+    pub fn track_session_words(&self, project_path: &str, doc_id: &str, words: impl FnOnce() -> usize) -> Option<(String, i64)> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let session = sessions.get_mut(Path::new(project_path))?;
+        session.latest.insert(doc_id.to_string(), words());
+        Some((session.id.clone(), session.words_added()))
+    }
+
+    /// Called as a long operation starts, so a stale cancel doesn't abort it.
+    pub fn begin_operation(&self) {
+        self.cancel.store(false, Ordering::SeqCst);
+    }
+
+    /// Ask whatever long operation is running to stop at its next checkpoint.
+    pub fn request_cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// Polled between files by long operations.
+    pub fn check_cancelled(&self) -> std::result::Result<(), AppError> {
+        if self.cancel.load(Ordering::SeqCst) {
+            return Err(AppError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+fn build_pool(project_path: &Path, readonly: bool) -> std::result::Result<Pool, AppError> {
+    let dbp = project_path.join("project.db");
+    // The pool would happily create an empty db; refuse instead.
+    if !dbp.is_file() {
+        return Err(AppError::NotFound(format!("no project database at {}", dbp.display())));
+    }
+    let manager = SqliteConnectionManager::file(&dbp)
+        .with_flags(open_flags(readonly))
+        .with_init(move |c| c.execute_batch(init_sql(readonly)));
+    Ok(r2d2::Pool::builder().max_size(4).build(manager)?)
+}
+
+/// Run `f` in a transaction, committing if it returns `Ok`. On `Err` the
+/// transaction is dropped, which rolls back everything `f` wrote.
+pub fn with_tx<T, E: From<rusqlite::Error>>(
+    conn: &mut Connection,
+    f: impl FnOnce(&Transaction) -> std::result::Result<T, E>,
+) -> std::result::Result<T, E> {
+    let tx = conn.transaction()?;
+    let out = f(&tx)?;
+    tx.commit()?;
+    Ok(out)
+}
+
+/// Ordered schema migrations. Migration N (1-based) brings a project to
+/// `PRAGMA user_version = N`; append new files here, never edit shipped ones.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_init.sql"),
+    include_str!("../migrations/0002_trash.sql"),
+    include_str!("../migrations/0003_sort_order.sql"),
+    include_str!("../migrations/0004_settings.sql"),
+    include_str!("../migrations/0005_character_relations.sql"),
+    include_str!("../migrations/0006_tags.sql"),
+    include_str!("../migrations/0007_folder_trash.sql"),
+    include_str!("../migrations/0008_document_lock.sql"),
+    include_str!("../migrations/0009_fts_tokenizer.sql"),
+    include_str!("../migrations/0010_templates.sql"),
+    include_str!("../migrations/0011_mirror_paths.sql"),
+    include_str!("../migrations/0012_orphan_cleanup.sql"),
+    include_str!("../migrations/0013_character_thumbnail.sql"),
+    include_str!("../migrations/0014_document_status.sql"),
+    include_str!("../migrations/0015_target_words.sql"),
+    include_str!("../migrations/0016_writing_sessions.sql"),
+    include_str!("../migrations/0017_character_age_years.sql"),
+    include_str!("../migrations/0018_undo_log.sql"),
+    include_str!("../migrations/0019_document_pinned.sql"),
+    include_str!("../migrations/0020_character_images.sql"),
+];
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut st = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let found = st
+        .query_map([], |r| r.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|n| n == column);
+    Ok(found)
+}
+
+// Projects created before user_version tracking sit at version 0 whatever
+// their schema; work out which migrations they already have from the columns.
+fn legacy_version(conn: &Connection) -> Result<i64> {
+    if !has_column(conn, "Document", "id")? {
+        return Ok(0);
+    }
+    if !has_column(conn, "Document", "deleted_at")? {
+        return Ok(1);
+    }
+    if !has_column(conn, "Document", "sort_order")? {
+        return Ok(2);
+    }
+    Ok(3)
+}
+
+/// Whether the project already has every migration applied.
+pub fn schema_is_current(conn: &Connection) -> Result<bool> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    Ok(version >= MIGRATIONS.len() as i64)
+}
+
+/// Apply every migration newer than the project's `user_version`, each in its
+/// own transaction together with the version bump.
 pub fn run_migrations(conn: &mut Connection) -> Result<()> {
-    conn.execute_batch(include_str!("../migrations/0001_init.sql"))?;
+    let mut version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    if version == 0 {
+        version = legacy_version(conn)?;
+        conn.pragma_update(None, "user_version", version)?;
+    }
+
+    for (i, sql) in MIGRATIONS.iter().enumerate() {
+        let target = i as i64 + 1;
+        if target <= version {
+            continue;
+        }
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", target)?;
+        tx.commit()?;
+    }
     Ok(())
 }
 
-pub fn select_docs(conn: &Connection) -> Result<Vec<serde_json::Value>> {
+// `updatedAt` is the later of the Document row (renames, moves) and its Body
+// (content edits), so a "recently edited" view sees both.
+pub fn select_docs(conn: &Connection) -> Result<Vec<Doc>> {
+    query_docs(conn, "")
+}
+
+// Just the pinned ones, in the same order.
+pub fn select_pinned_docs(conn: &Connection) -> Result<Vec<Doc>> {
+    query_docs(conn, "AND d.pinned = 1")
+}
+
+fn query_docs(conn: &Connection, filter: &str) -> Result<Vec<Doc>> {
+    let mut st = conn.prepare(&format!(
+        "SELECT d.id, d.title, d.folder_id, d.created_at,
+                MAX(d.updated_at, COALESCE(b.updated_at, d.updated_at)), d.locked, d.status, d.pinned
+         FROM Document d
+         LEFT JOIN Body b ON b.document_id = d.id
+         WHERE d.deleted_at IS NULL {filter}
+         ORDER BY d.sort_order ASC, d.created_at ASC"
+    ))?;
+    let rows = st.query_map([], |r| {
+        Ok(Doc {
+            id: r.get(0)?,
+            title: r.get(1)?,
+            folder_id: r.get(2)?,
+            created_at: r.get(3)?,
+            updated_at: r.get(4)?,
+            locked: r.get(5)?,
+            status: r.get(6)?,
+            pinned: r.get(7)?,
+        })
+    })?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+pub fn select_trashed_docs(conn: &Connection) -> Result<Vec<serde_json::Value>> {
     let mut st = conn.prepare(
-        "SELECT id, title, folder_id
+        "SELECT id, title, folder_id, deleted_at
          FROM Document
-         ORDER BY created_at ASC",
+         WHERE deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC",
     )?;
     let rows = st.query_map([], |r| {
         Ok(serde_json::json!({
             "id": r.get::<_, String>(0)?,
             "title": r.get::<_, String>(1)?,
             "folderId": r.get::<_, Option<String>>(2)?,
+            "deletedAt": r.get::<_, String>(3)?,
         }))
     })?;
     Ok(rows.filter_map(|r| r.ok()).collect())
 }
 
-pub fn select_folders(conn: &Connection) -> Result<Vec<serde_json::Value>> {
+pub fn select_trashed_folders(conn: &Connection) -> Result<Vec<serde_json::Value>> {
     let mut st = conn.prepare(
-        "SELECT id, name, parent_id
+        "SELECT id, name, parent_id, deleted_at
          FROM Folder
-         ORDER BY name ASC",
+         WHERE deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC",
     )?;
     let rows = st.query_map([], |r| {
         Ok(serde_json::json!({
             "id": r.get::<_, String>(0)?,
             "name": r.get::<_, String>(1)?,
             "parentId": r.get::<_, Option<String>>(2)?,
+            "deletedAt": r.get::<_, String>(3)?,
         }))
     })?;
     Ok(rows.filter_map(|r| r.ok()).collect())
 }
 
-pub fn select_chars(conn: &Connection) -> Result<Vec<serde_json::Value>> {
+pub fn select_folders(conn: &Connection) -> Result<Vec<Folder>> {
+    let mut st = conn.prepare(
+        "SELECT id, name, parent_id
+         FROM Folder
+         WHERE deleted_at IS NULL
+         ORDER BY name ASC",
+    )?;
+    let rows = st.query_map([], |r| {
+        Ok(Folder { id: r.get(0)?, name: r.get(1)?, parent_id: r.get(2)?, doc_count: None, word_count: None })
+    })?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+// Characters for the tree; `folderId` is null for characters at the project root.
+pub fn select_chars(conn: &Connection) -> Result<Vec<CharacterSummary>> {
     let mut st = conn.prepare(
         "SELECT id, name, folder_id
          FROM Character
-         ORDER BY created_at ASC",
+         WHERE deleted_at IS NULL
+         ORDER BY name ASC",
     )?;
     let rows = st.query_map([], |r| {
-        Ok(serde_json::json!({
-            "id": r.get::<_, String>(0)?,
-            "name": r.get::<_, String>(1)?,
-            "folderId": r.get::<_, Option<String>>(2)?,
-        }))
+        Ok(CharacterSummary { id: r.get(0)?, name: r.get(1)?, folder_id: r.get(2)? })
     })?;
     Ok(rows.filter_map(|r| r.ok()).collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pooled_connections_cascade_document_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().into_owned();
+        run_migrations(&mut open_conn(&path, false).unwrap()).unwrap();
+
+        let state = AppState::default();
+        state.open(&path, false).unwrap();
+        // Hold one connection so the second comes from a different pool slot.
+        let first = state.conn_mut(&path).unwrap();
+        let second = state.conn_mut(&path).unwrap();
+        for conn in [&first, &second] {
+            let fk: i64 = conn.query_row("PRAGMA foreign_keys", [], |r| r.get(0)).unwrap();
+            assert_eq!(fk, 1);
+        }
+
+        first
+            .execute_batch(
+                "INSERT INTO Document(id, title) VALUES('d1', 'Doc');
+                 INSERT INTO Body(document_id, markdown) VALUES('d1', 'text');
+                 INSERT INTO Snapshot(id, document_id, markdown) VALUES('s1', 'd1', 'old');",
+            )
+            .unwrap();
+        second.execute("DELETE FROM Document WHERE id='d1'", []).unwrap();
+
+        let left = |table: &str| -> i64 {
+            first.query_row(&format!("SELECT COUNT(*) FROM {table} WHERE document_id='d1'"), [], |r| r.get(0)).unwrap()
+        };
+        assert_eq!(left("Body"), 0);
+        assert_eq!(left("Snapshot"), 0);
+    }
+
+    #[test]
+    fn tree_rows_serialize_camel_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = open_conn(dir.path(), false).unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO Folder(id, name) VALUES('f1', 'Root');
+             INSERT INTO Folder(id, parent_id, name) VALUES('f2', 'f1', 'Child');
+             INSERT INTO Document(id, title) VALUES('d1', 'Loose');
+             INSERT INTO Document(id, title, folder_id) VALUES('d2', 'Filed', 'f2');
+             INSERT INTO Character(id, project_id, name) VALUES('c1', 'p1', 'Ada');
+             INSERT INTO Character(id, project_id, folder_id, name) VALUES('c2', 'p1', 'f1', 'Bo');",
+        )
+        .unwrap();
+
+        let by_id = |rows: Vec<serde_json::Value>, id: &str| rows.into_iter().find(|r| r["id"] == id).unwrap();
+        let docs = || select_docs(&conn).unwrap().iter().map(|d| serde_json::to_value(d).unwrap()).collect();
+        let folders = || select_folders(&conn).unwrap().iter().map(|f| serde_json::to_value(f).unwrap()).collect();
+        let chars = || select_chars(&conn).unwrap().iter().map(|c| serde_json::to_value(c).unwrap()).collect();
+
+        let doc = by_id(docs(), "d1");
+        assert_eq!(doc["folderId"], serde_json::Value::Null);
+        assert!(doc.get("folder_id").is_none());
+        for key in ["createdAt", "updatedAt"] {
+            assert!(doc.get(key).is_some(), "missing {key} in {doc}");
+        }
+        assert_eq!(by_id(docs(), "d2")["folderId"], "f2");
+
+        let folder = by_id(folders(), "f1");
+        assert_eq!(folder["parentId"], serde_json::Value::Null);
+        assert!(folder.get("parent_id").is_none());
+        assert_eq!(by_id(folders(), "f2")["parentId"], "f1");
+
+        let character = by_id(chars(), "c1");
+        assert_eq!(character["folderId"], serde_json::Value::Null);
+        assert!(character.get("folder_id").is_none());
+        assert_eq!(by_id(chars(), "c2")["folderId"], "f1");
+    }
+}
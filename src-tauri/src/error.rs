@@ -0,0 +1,56 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Error returned by every command. Serialized as `{ kind, message }` so the
+/// frontend can branch on `kind` instead of parsing strings.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error(transparent)]
+    Db(rusqlite::Error),
+    #[error(transparent)]
+    Pool(#[from] r2d2::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Validation(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("cancelled")]
+    Cancelled,
+}
+
+impl AppError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::Db(_) | AppError::Pool(_) => "db",
+            AppError::Io(_) => "io",
+            AppError::Zip(_) => "zip",
+            AppError::NotFound(_) => "notFound",
+            AppError::Validation(_) => "validation",
+            AppError::Conflict(_) => "conflict",
+            AppError::Cancelled => "cancelled",
+        }
+    }
+}
+
+// A single-row query that matched nothing means the id doesn't exist.
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::NotFound("not found".into()),
+            e => AppError::Db(e),
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut st = serializer.serialize_struct("AppError", 2)?;
+        st.serialize_field("kind", self.kind())?;
+        st.serialize_field("message", &self.to_string())?;
+        st.end()
+    }
+}
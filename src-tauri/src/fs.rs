@@ -1,29 +1,34 @@
 // src-tauri/src/fs.rs
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use anyhow::{anyhow, Context, Result};
 
-/// Change this to your app's workspace root resolver.
-/// If you already have one, use that instead and remove this function.
-fn workspace_root() -> Result<PathBuf> {
-    // Example: use the current_dir as the project root.
-    // In production, prefer an app config location (e.g., app_dir).
-    std::env::current_dir().context("Failed to get current_dir as workspace root")
+/// Refuse paths that don't resolve to somewhere inside the open project.
+fn ensure_within_workspace(project_path: &str, abs_path: &Path) -> Result<()> {
+    let abs = abs_path.canonicalize().with_context(|| format!("canonicalize: {}", abs_path.display()))?;
+    let root_canon = Path::new(project_path)
+        .canonicalize()
+        .with_context(|| format!("canonicalize project root: {project_path}"))?;
+    if !abs.starts_with(&root_canon) {
+        return Err(anyhow!("Refusing to delete outside the project root"));
+    }
+    Ok(())
 }
 
-fn ensure_within_workspace(abs_path: &Path) -> Result<()> {
-    let root = workspace_root()?;
+/// The project root itself, `md/` and `project.db` are never delete targets.
+fn ensure_not_protected(project_path: &str, abs_path: &Path) -> Result<()> {
+    let root = Path::new(project_path).canonicalize().context("canonicalize project root")?;
     let abs = abs_path.canonicalize().with_context(|| format!("canonicalize: {}", abs_path.display()))?;
-    let root_canon = root.canonicalize().context("canonicalize root")?;
-    if !abs.starts_with(&root_canon) {
-        return Err(anyhow!("Refusing to delete outside workspace root"));
+    if abs == root || abs == root.join("md") || abs == root.join("project.db") {
+        return Err(anyhow!("refusing to delete protected path"));
     }
     Ok(())
 }
 
-pub fn delete_folder_recursive(abs_path: &str) -> Result<()> {
+pub fn delete_folder_recursive(project_path: &str, abs_path: &str) -> Result<()> {
     let p = Path::new(abs_path);
-    ensure_within_workspace(p)?;
+    ensure_within_workspace(project_path, p)?;
+    ensure_not_protected(project_path, p)?;
     if !p.is_dir() {
         return Err(anyhow!("Path is not a folder: {}", abs_path));
     }
@@ -31,9 +36,9 @@ pub fn delete_folder_recursive(abs_path: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn delete_doc(abs_path: &str) -> Result<()> {
+pub fn delete_doc(project_path: &str, abs_path: &str) -> Result<()> {
     let p = Path::new(abs_path);
-    ensure_within_workspace(p)?;
+    ensure_within_workspace(project_path, p)?;
     if !p.is_file() {
         return Err(anyhow!("Path is not a file: {}", abs_path));
     }
@@ -41,10 +46,10 @@ pub fn delete_doc(abs_path: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn delete_character(abs_path: &str) -> Result<()> {
+pub fn delete_character(project_path: &str, abs_path: &str) -> Result<()> {
     // If characters are stored as files, this is same as doc; if a folder, adjust as needed.
     let p = Path::new(abs_path);
-    ensure_within_workspace(p)?;
+    ensure_within_workspace(project_path, p)?;
     if p.is_dir() {
         // Some apps keep a character as a folder — handle both gracefully.
         fs::remove_dir_all(p).with_context(|| format!("remove_dir_all (character) failed: {}", abs_path))?;
@@ -55,3 +60,44 @@ pub fn delete_character(abs_path: &str) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deletes_are_checked_against_the_project_not_current_dir() {
+        let project = tempfile::tempdir().unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        let project_path = project.path().to_string_lossy().into_owned();
+        let inside = project.path().join("a.md");
+        let outside = elsewhere.path().join("b.md");
+        fs::write(&inside, "x").unwrap();
+        fs::write(&outside, "x").unwrap();
+
+        assert!(delete_doc(&project_path, &outside.to_string_lossy()).is_err());
+        assert!(outside.is_file());
+        let escaping = project.path().join("..").join(elsewhere.path().file_name().unwrap()).join("b.md");
+        assert!(delete_doc(&project_path, &escaping.to_string_lossy()).is_err());
+        assert!(outside.is_file());
+
+        delete_doc(&project_path, &inside.to_string_lossy()).unwrap();
+        assert!(!inside.exists());
+    }
+
+    #[test]
+    fn folder_delete_refuses_root_and_md() {
+        let project = tempfile::tempdir().unwrap();
+        let project_path = project.path().to_string_lossy().into_owned();
+        fs::create_dir_all(project.path().join("md")).unwrap();
+        fs::create_dir_all(project.path().join("assets")).unwrap();
+
+        for protected in [project_path.clone(), format!("{project_path}/md"), format!("{project_path}/assets/../md")] {
+            let err = delete_folder_recursive(&project_path, &protected).unwrap_err();
+            assert_eq!(err.to_string(), "refusing to delete protected path");
+        }
+        assert!(project.path().join("md").is_dir());
+        delete_folder_recursive(&project_path, &format!("{project_path}/assets")).unwrap();
+        assert!(!project.path().join("assets").exists());
+    }
+}
@@ -1,17 +1,106 @@
 use std::fs::{self, File};
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn atomic_write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp = stage_write(path, bytes)?;
+    commit_staged(&tmp, path)
+}
+
+/// The first half of `atomic_write`: write `bytes` to a fresh temp file beside
+/// `path` and return it, for callers that rename it in with `commit_staged`
+/// only once something else (a db transaction) has succeeded.
+pub fn stage_write(path: &Path, bytes: &[u8]) -> io::Result<PathBuf> {
+    let tmp = unique_tmp_path(path);
+    if let Err(e) = write_synced(&tmp, bytes) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(tmp)
+}
+
+/// Rename a `stage_write` temp file onto `path`, removing it if that fails.
+pub fn commit_staged(tmp: &Path, path: &Path) -> io::Result<()> {
+    if let Err(e) = fs::rename(tmp, path) {
+        let _ = fs::remove_file(tmp);
+        return Err(e);
+    }
+    sync_parent_dir(path)
+}
+
+// `.<name>.<pid>.<nanos>.<seq>.tmp` beside `path`: the same directory keeps
+// the rename on one filesystem, and a name per call means two writes racing
+// on one file (autosave vs. a manual save) never share a temp file.
+fn unique_tmp_path(path: &Path) -> PathBuf {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{name}.{}.{nanos}.{seq}.tmp", std::process::id()))
+}
+
+// The rename lives in the parent directory's entries, and on Unix it isn't
+// durable until that directory is fsynced: a crash right after could lose the
+// new file. Windows won't open a directory as a plain `File` to sync it and
+// relies on NTFS's metadata journal instead, so it's a no-op there.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Write `bytes` to `path` and fsync it, creating parent dirs as needed.
+pub fn write_synced(path: &Path, bytes: &[u8]) -> io::Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
-    let tmp = path.with_extension("tmp");
-    {
-        let mut f = File::create(&tmp)?;
-        f.write_all(bytes)?;
-        f.sync_all()?;
+    let mut f = File::create(path)?;
+    f.write_all(bytes)?;
+    f.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_writes_never_interleave() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("doc.md");
+        let payloads: Vec<Vec<u8>> = (0..8u8).map(|t| vec![b'a' + t; 64 * 1024 + t as usize]).collect();
+
+        let handles: Vec<_> = payloads
+            .iter()
+            .cloned()
+            .map(|payload| {
+                let target = target.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..25 {
+                        atomic_write(&target, &payload).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let last = fs::read(&target).unwrap();
+        assert!(payloads.contains(&last), "final content is not one whole write");
+        let leftovers: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .filter(|n| n.to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty(), "temp files left behind: {leftovers:?}");
     }
-    fs::rename(&tmp, path)?;
-    Ok(())
 }
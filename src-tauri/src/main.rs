@@ -4,28 +4,106 @@
 
 mod commands;
 mod db;
+mod error;
 mod fs_utils;
 
 // If you prefer, you can explicitly import the commands you expose.
 // This helps catch typos at compile-time and keeps generate_handler! tidy.
 use commands::{
+  add_relation,
+  add_tag,
+  audit_project,
   backup_project,
+  backup_project_incremental,
+  cancel_operation,
   create_character,
   create_document,
+  create_document_from_template,
   create_folder,
   create_project,
   create_snapshot,
   delete_character,
   delete_doc,
+  delete_documents,
   delete_folder_recursive,
+  delete_snapshot,
+  diff_snapshots,
+  document_stats,
+  duplicate_document,
+  duplicate_folder,
+  empty_trash,
+  end_session,
+  export_character,
+  export_document_html,
+  export_document_pdf,
+  export_docx,
+  export_epub,
+  export_manuscript,
+  export_markdown_bundle,
+  get_document,
+  get_setting,
+  health_check,
   import_character_image,
+  import_character_images,
+  import_markdown_dir,
+  list_backups,
+  list_characters,
+  list_characters_sorted,
+  list_documents_by_tag,
+  list_pinned,
+  list_recent_projects,
+  list_relations,
+  list_snapshots,
+  list_tags,
+  list_templates,
+  list_trash,
+  list_trashed_folders,
   list_tree,
+  list_tree_nested,
   load_character,
   load_document,
+  load_document_range,
+  move_character,
+  move_document,
+  move_folder,
+  move_items,
   open_project,
+  project_info,
+  project_stats,
+  prune_backups,
+  prune_snapshots,
+  rebuild_search_index,
+  record_opened_project,
+  relayout_mirrors,
+  remove_recent_project,
+  remove_relation,
+  remove_tag,
+  rename_character,
+  rename_document,
+  reorder_documents,
+  replace_all,
+  restore_folder,
+  restore_project,
+  restore_snapshot,
+  restore_trashed,
   save_character,
   save_document,
+  save_document_as_template,
   search,
+  search_characters,
+  search_regex,
+  session_history,
+  set_document_locked,
+  set_document_pinned,
+  set_document_status,
+  set_document_target_words,
+  set_project_target_words,
+  set_setting,
+  start_session,
+  trash_document,
+  trash_folder,
+  undo_last,
+  verify_backup,
 };
 
 fn main() {
@@ -34,37 +112,132 @@ fn main() {
     .plugin(tauri_plugin_dialog::init())
     // If you later add more plugins (e.g., shell/fs/path), chain them here.
 
+    // Per-project SQLite connection pools, borrowed by every command.
+    .manage(db::AppState::default())
+
     // Register every IPC command exposed to the frontend.
     .invoke_handler(tauri::generate_handler![
       // Project lifecycle
       create_project,
       open_project,
+      record_opened_project,
+      list_recent_projects,
+      remove_recent_project,
       backup_project,
+      backup_project_incremental,
+      cancel_operation,
+      list_backups,
+      prune_backups,
+      verify_backup,
+      health_check,
+      audit_project,
+      relayout_mirrors,
+      restore_project,
+      get_setting,
+      set_setting,
 
       // Tree & content CRUD
       list_tree,
+      list_tree_nested,
       create_folder,
       create_document,
       create_character,
+      duplicate_document,
+      rename_document,
+      set_document_locked,
+      set_document_status,
+      set_document_pinned,
+      list_pinned,
+      duplicate_folder,
+      move_document,
+      move_folder,
+      move_character,
+      move_items,
+      reorder_documents,
+      import_markdown_dir,
 
       // Load/save content
       load_document,
+      load_document_range,
+      get_document,
       save_document,
       load_character,
+      list_characters,
       save_character,
+      rename_character,
+      list_characters_sorted,
+      document_stats,
+      set_document_target_words,
+      project_stats,
+      set_project_target_words,
+      project_info,
       import_character_image,
+      import_character_images,
+
+      // Writing sessions
+      start_session,
+      end_session,
+      session_history,
+
+      // Templates
+      save_document_as_template,
+      create_document_from_template,
+      list_templates,
+
+      // Tags
+      add_tag,
+      remove_tag,
+      list_tags,
+      list_documents_by_tag,
+
+      // Character relations
+      add_relation,
+      remove_relation,
+      list_relations,
 
       // Search/snapshots
       search,
+      search_characters,
+      search_regex,
+      rebuild_search_index,
+      replace_all,
       create_snapshot,
+      list_snapshots,
+      restore_snapshot,
+      diff_snapshots,
+      delete_snapshot,
+      prune_snapshots,
+
+      // Export
+      export_document_html,
+      export_document_pdf,
+      export_manuscript,
+      export_epub,
+      export_docx,
+      export_markdown_bundle,
+      export_character,
 
       // **Deletions** (required for Section B)
       // - delete_folder_recursive: removes a folder and ALL nested content
       // - delete_doc: deletes a single document
+      // - delete_documents: deletes many documents in one transaction
       // - delete_character: deletes a single character (file or dir, depending on your impl)
+      // - trash_document / restore_trashed / list_trash: soft delete; delete_doc or
+      //   empty_trash remove trashed docs for good
+      // - trash_folder / restore_folder / list_trashed_folders: soft delete for a whole subtree
+      // - undo_last: puts back the most recent delete_doc / delete_folder_recursive
       delete_folder_recursive,
       delete_doc,
-      delete_character
+      delete_documents,
+      delete_character,
+      trash_document,
+      restore_trashed,
+      list_trash,
+      trash_folder,
+      restore_folder,
+      list_trashed_folders,
+      empty_trash,
+      undo_last
     ])
     // Optional: do any runtime checks or logging here.
     // .setup(|_app| { Ok(()) })
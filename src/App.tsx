@@ -5,7 +5,7 @@ import Editor from "./features/editor/Editor";
 import { useSnapshot } from "valtio";
 import { state } from "./lib/store";
 import { open } from "@tauri-apps/plugin-dialog";
-import { createProject, openProject, loadDoc, listTree, backupProject } from "./lib/ipc";
+import { createProject, openProject, loadDoc, listTree, backupProject, errorMessage } from "./lib/ipc";
 
 export default function App() {
   const s = useSnapshot(state);
@@ -27,7 +27,7 @@ export default function App() {
       const { docs } = await listTree(state.projectPath);
       if (docs[0]) state.currentDocId = docs[0].id;
     } catch (err) {
-      alert(`Failed to open directory: ${err}`);
+      alert(`Failed to open directory: ${errorMessage(err)}`);
       console.error(err);
     }
   };
@@ -128,7 +128,7 @@ export default function Tree() {
     if (!state.projectPath) return alert("Open or create a project first.");
     const name = promptName("New character name?");
     if (name === null) return;
-    const id = await newCharacter(state.projectPath, name, folderId);
+    const { id } = await newCharacter(state.projectPath, name, folderId);
     await refresh();
     selectChar(id);
     if (folderId) setExpanded((e) => ({ ...e, [folderId]: true }));
@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Doc = { id: string, title: string, folderId: string | null, createdAt: string | null, updatedAt: string | null, locked: boolean, status: string | null, pinned: boolean, };
+
+export type Folder = { id: string, name: string, parentId: string | null, docCount?: number, wordCount?: number, };
+
+export type CharacterSummary = { id: string, name: string, folderId: string | null, };
+
+/**
+ * Everything live in the project, as flat lists (list_tree_nested nests them).
+ */
+export type Tree = { docs: Array<Doc>, folders: Array<Folder>, characters: Array<CharacterSummary>, };
+
+export type Attribute = { key: string, value: string, };
+
+export type SearchHit = { id: string, title: string, snippet: string, };
+
+export type SearchPage = { hits: Array<SearchHit>, total: number, };
+
+/**
+ * One entry per `source_paths` item from import_character_images.
+ */
+export type ImageImportResult = { source: string, ok: boolean, error: string | null, };
@@ -1,17 +1,59 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import type { Doc, Folder, ImageImportResult, SearchPage, Tree } from "./bindings";
 
-// Project
-export const openProject = (dir: string) => invoke<string>("open_project", { dir });
-export const createProject = (dir: string, name: string) =>
-  invoke<string>("create_project", { dir, name });
+// Shapes shared with the Rust side live in ./bindings (generated by ts-rs).
+export type * from "./bindings";
+
+// Every command rejects with this shape (see src-tauri/src/error.rs).
+export type AppError = {
+  kind: "db" | "io" | "notFound" | "validation" | "conflict" | "zip" | "cancelled";
+  message: string;
+};
 
-// Tree (folders + docs + characters)
-export const listTree = (projectPath: string) =>
-  invoke<{ docs: any[]; folders: any[]; characters?: any[] }>("list_tree", {
+export const errorMessage = (err: unknown): string =>
+  typeof err === "object" && err !== null && "message" in err
+    ? String((err as AppError).message)
+    : String(err);
+
+// Project
+export const openProject = (dir: string, readonly = false) =>
+  invoke<string>("open_project", { dir, readonly });
+// Rejects with kind "conflict" if `dir/name` already holds a project, unless `overwrite`.
+export const createProject = (dir: string, name: string, overwrite = false) =>
+  invoke<string>("create_project", { dir, name, overwrite });
+
+// Recently opened projects (app-wide); `missing` means the folder is gone.
+export type RecentProject = { path: string; openedAt: string; missing: boolean };
+export const listRecentProjects = () => invoke<RecentProject[]>("list_recent_projects");
+export const removeRecentProject = (path: string) => invoke("remove_recent_project", { path });
+
+// Tree (folders + docs + characters). `includeCounts` adds each folder's
+// recursive docCount/wordCount.
+export const listTree = (projectPath: string, includeCounts = false) =>
+  invoke<Tree>("list_tree", {
     projectPath,
     project_path: projectPath,
+    includeCounts,
+    include_counts: includeCounts,
   });
 
+// Emitted after any command that changes the tree; refresh instead of polling listTree.
+export type TreeChange = {
+  kind: "created" | "deleted" | "trashed" | "restored" | "moved" | "renamed" | "reordered";
+  docIds: string[];
+  folderIds: string[];
+  characterIds: string[];
+};
+export const onTreeChanged = (handler: (change: TreeChange) => void) =>
+  listen<TreeChange>("tree-changed", (event) => handler(event.payload));
+
+// Emitted by openProject when its trash-retention purge removed anything or
+// failed; `error` is null on success. Subscribe before opening.
+export type TrashPurged = { projectPath: string; purged: number; error: string | null };
+export const onTrashPurged = (handler: (outcome: TrashPurged) => void) =>
+  listen<TrashPurged>("trash-purged", (event) => handler(event.payload));
+
 // Docs
 export const loadDoc = (projectPath: string, docId: string) =>
   invoke<string>("load_document", {
@@ -22,7 +64,7 @@ export const loadDoc = (projectPath: string, docId: string) =>
   });
 
 export const saveDoc = (projectPath: string, docId: string, markdown: string) =>
-  invoke("save_document", {
+  invoke<string>("save_document", {
     projectPath,
     project_path: projectPath,
     docId,
@@ -41,10 +83,16 @@ export const newDoc = (projectPath: string, title: string, folderId?: string | n
   });
 };
 
+// Pinned docs show with a star in the tree and in the quick-access list.
+export const setDocumentPinned = (projectPath: string, docId: string, pinned: boolean) =>
+  invoke("set_document_pinned", { projectPath, project_path: projectPath, docId, doc_id: docId, pinned });
+export const listPinned = (projectPath: string) =>
+  invoke<Doc[]>("list_pinned", { projectPath, project_path: projectPath });
+
 // Folders
 export const newFolder = (projectPath: string, name: string, parentId?: string | null) => {
   const parentValue = parentId ?? null;
-  return invoke<string>("create_folder", {
+  return invoke<Folder>("create_folder", {
     projectPath,
     project_path: projectPath,
     name,
@@ -55,7 +103,15 @@ export const newFolder = (projectPath: string, name: string, parentId?: string |
 
 // Search
 export const doSearch = (projectPath: string, q: string) =>
-  invoke<Array<[string, string]>>("search", { projectPath, project_path: projectPath, q });
+  invoke<SearchPage>("search", { projectPath, project_path: projectPath, q });
+export const doRegexSearch = (projectPath: string, pattern: string, ftsFilter?: string | null) =>
+  invoke<SearchPage>("search_regex", {
+    projectPath,
+    project_path: projectPath,
+    pattern,
+    ftsFilter: ftsFilter ?? null,
+    fts_filter: ftsFilter ?? null,
+  });
 
 // Snapshots / backup
 export const snapshotDoc = (projectPath: string, docId: string, note: string) =>
@@ -67,14 +123,19 @@ export const snapshotDoc = (projectPath: string, docId: string, note: string) =>
     note,
   });
 
-export const backupProject = (projectPath: string) =>
-  invoke("backup_project", { projectPath, project_path: projectPath });
+export type BackupCompression = "store" | "fast" | "best";
+export const backupProject = (projectPath: string, compression?: BackupCompression) =>
+  invoke("backup_project", { projectPath, project_path: projectPath, compression });
+export const backupProjectIncremental = (projectPath: string, compression?: BackupCompression) =>
+  invoke("backup_project_incremental", { projectPath, project_path: projectPath, compression });
+// Aborts a running backup or export; that call rejects with kind "cancelled".
+export const cancelOperation = () => invoke("cancel_operation");
 
 // ------------------ Characters ------------------
 
 export const newCharacter = (projectPath: string, name: string, folderId?: string | null) => {
   const folderValue = folderId ?? null;
-  return invoke<string>("create_character", {
+  return invoke<{ id: string; name: string; folderId: string | null; [field: string]: unknown }>("create_character", {
     projectPath,
     project_path: projectPath,
     name,
@@ -115,6 +176,17 @@ export const importCharacterImage = (
     source_path: sourcePath,
   });
 
+// Drag-drop import of many reference images; one result per source path, in order.
+export const importCharacterImages = (projectPath: string, charId: string, sourcePaths: string[]) =>
+  invoke<ImageImportResult[]>("import_character_images", {
+    projectPath,
+    project_path: projectPath,
+    charId,
+    char_id: charId,
+    sourcePaths,
+    source_paths: sourcePaths,
+  });
+
   export async function deleteFolderRecursive(
   projectPath: string,
   folderId: string,
@@ -140,6 +212,15 @@ export async function deleteDoc(projectPath: string, docId: string) {
   });
 }
 
+// Put back the most recent deleteDoc / deleteFolderRecursive; rejects with
+// kind "notFound" when there's nothing left to undo. Bulk deletes
+// (delete_documents), deleteCharacter and emptying the trash are permanent.
+export const undoLast = (projectPath: string) =>
+  invoke<{ kind: "delete_doc" | "delete_folder"; label: string }>("undo_last", {
+    projectPath,
+    project_path: projectPath,
+  });
+
 /**
  * Delete a single character by id.  Removes the row from the database
  * and deletes any character asset directory under `assets/characters`.
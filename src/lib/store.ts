@@ -28,6 +28,7 @@ export const state = proxy({
 
   search: {
     q: "",
-    results: [] as Array<{ id: string; snippet: string }>,
+    results: [] as Array<{ id: string; title: string; snippet: string }>,
+    total: 0,
   },
 });